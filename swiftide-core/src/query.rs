@@ -140,6 +140,11 @@ impl Query<states::Retrieved> {
         &self.state.documents
     }
 
+    /// Returns true if retrieval succeeded but did not match any documents
+    pub fn is_empty(&self) -> bool {
+        self.state.documents.is_empty()
+    }
+
     /// Transition the query to `states::Answered`
     #[must_use]
     pub fn answered(self, answer: impl Into<String>) -> Query<states::Answered> {
@@ -298,6 +303,18 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_query_retrieved_no_documents_is_empty() {
+        let query = Query::<states::Pending>::from("test query");
+        let query = query.retrieved_documents(vec![]);
+        assert!(query.is_empty());
+        assert_eq!(query.history().len(), 1);
+
+        let query = Query::<states::Pending>::from("test query");
+        let query = query.retrieved_documents(vec!["doc1".to_string()]);
+        assert!(!query.is_empty());
+    }
+
     #[test]
     fn test_query_retrieved_documents() {
         let query = Query::<states::Pending>::from("test query");
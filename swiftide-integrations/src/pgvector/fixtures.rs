@@ -38,7 +38,7 @@
 //! - Combined embedding modes
 //! - Different vector configurations
 //! - Various metadata scenarios
-use crate::pgvector::PgVector;
+use crate::pgvector::{PgVector, PgVectorBuilder};
 use std::collections::HashSet;
 use swiftide_core::{
     indexing::{self, EmbeddedField},
@@ -142,6 +142,16 @@ impl TestContext {
     pub(crate) async fn setup_with_cfg(
         metadata_fields: Option<Vec<&str>>,
         vector_fields: HashSet<EmbeddedField>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::setup_with_builder(metadata_fields, vector_fields, |builder| builder).await
+    }
+
+    /// Set up the test context like [`TestContext::setup_with_cfg`], allowing additional
+    /// builder options to be applied before `PgVector` storage is built
+    pub(crate) async fn setup_with_builder(
+        metadata_fields: Option<Vec<&str>>,
+        vector_fields: HashSet<EmbeddedField>,
+        configure: impl FnOnce(&mut PgVectorBuilder) -> &mut PgVectorBuilder,
     ) -> Result<Self, Box<dyn std::error::Error>> {
         // Start `PostgreSQL` container and obtain the connection URL
         let (pgv_db_container, pgv_db_url) = swiftide_test_utils::start_postgres().await;
@@ -168,6 +178,8 @@ impl TestContext {
             }
         };
 
+        builder = configure(builder);
+
         let pgv_storage = builder.build().map_err(|err| {
             tracing::error!("Failed to build PgVector: {}", err);
             err
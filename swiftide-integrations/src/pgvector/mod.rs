@@ -26,17 +26,35 @@
 mod fixtures;
 
 mod persist;
+mod pgv_index;
+mod pgv_migrate;
+mod pgv_schema;
+mod pgv_storage;
 mod pgv_table_types;
 mod retrieve;
+mod scan;
 use anyhow::Result;
 use derive_builder::Builder;
-use sqlx::PgPool;
+use sqlx::{types::Uuid, PgPool};
 use std::fmt;
 use std::sync::Arc;
 use std::sync::OnceLock;
+use swiftide_core::indexing::EmbeddedField;
 use tokio::time::Duration;
 
-use pgv_table_types::{FieldConfig, MetadataConfig, VectorConfig};
+pub use pgv_schema::{ColumnDescription, TableDescription};
+pub use pgv_storage::{ColumnStorage, StorageBreakdown};
+use pgv_table_types::FieldConfig;
+pub use pgv_table_types::{
+    FullTextConfig, MetadataConfig, MetadataSerialization, VectorCompression, VectorConfig,
+    VectorIndexType, VectorSize,
+};
+pub use retrieve::{
+    BoolQuery, ChildChunk, Citation, CitationConfig, DiversityPenalty, DocumentWithNeighbors,
+    DocumentsWithDistances, NoEmbeddingBehavior, ParentGroup, ProjectedRow, Projection,
+    RetrieveOptions, SortOrder,
+};
+pub use scan::StoredNode;
 
 /// Default maximum connections for the database connection pool.
 const DB_POOL_CONN_MAX: u32 = 10;
@@ -50,6 +68,24 @@ const DB_POOL_CONN_RETRY_DELAY_SECS: u64 = 3;
 /// Default batch size for storing nodes.
 const BATCH_SIZE: usize = 50;
 
+/// Default maximum attempts of `setup` when the database is not ready yet.
+const SETUP_MAX_RETRIES: u32 = 5;
+
+/// Default delay before the first `setup` retry, in milliseconds. Doubles on every retry.
+const SETUP_RETRY_BACKOFF_MILLIS: u64 = 500;
+
+/// Default maximum attempts of a retrieve hitting serialization failures.
+const RETRIEVE_MAX_RETRIES: u32 = 3;
+
+/// Default delay between retrieve attempts after a serialization failure, in milliseconds.
+const RETRIEVE_RETRY_DELAY_MILLIS: u64 = 100;
+
+/// Default maximum reconnect attempts per page when scanning the table.
+const SCAN_MAX_RECONNECTS: u32 = 3;
+
+/// Default maximum number of rows returned by a radius search.
+const RADIUS_SEARCH_LIMIT: usize = 10_000;
+
 /// Represents a Pgvector client with configuration options.
 ///
 /// This struct is used to interact with the Pgvector vector database, providing methods to manage vector collections,
@@ -61,8 +97,21 @@ pub struct PgVector {
     #[builder(default = "String::from(\"swiftide_pgv_store\")")]
     table_name: String,
 
-    /// Default vector size; can be customized per configuration.
-    vector_size: i32,
+    /// Table-wide vector size, used by vector fields without their own size.
+    ///
+    /// A size set on a field with [`VectorConfig::with_vector_size`] takes precedence. Every
+    /// vector field needs either its own size or this one. Use [`VectorSize::Auto`] to infer the
+    /// size from the first stored node instead.
+    #[builder(default)]
+    vector_size: Option<VectorSize>,
+
+    /// Vector size inferred from the first stored node when using [`VectorSize::Auto`].
+    #[builder(private, default = "Arc::new(OnceLock::new())")]
+    inferred_vector_size: Arc<OnceLock<i32>>,
+
+    /// Set once the schema for the inferred vector size was created successfully.
+    #[builder(private, default = "Arc::new(OnceLock::new())")]
+    inferred_schema_created: Arc<OnceLock<()>>,
 
     /// Batch size for storing nodes.
     #[builder(default = "BATCH_SIZE")]
@@ -74,9 +123,119 @@ pub struct PgVector {
     #[builder(default)]
     fields: Vec<FieldConfig>,
 
+    /// Relation that retrieves read from instead of the table, e.g. a materialized view.
+    ///
+    /// Useful when retrieval always joins the stored rows with other data: the join can be
+    /// precomputed in a view exposing the columns retrieval selects. Stores always target the
+    /// table, so a materialized view has to be refreshed to see new rows.
+    #[builder(default)]
+    retrieve_from: Option<String>,
+
+    /// Vector field ranked on by similarity retrieval.
+    ///
+    /// Required when several vector fields are configured, see
+    /// [`PgVector::switch_ranking_field`].
+    #[builder(default)]
+    ranking_field: Option<EmbeddedField>,
+
+    /// Safety cap on the number of rows returned by [`PgVector::retrieve_within_radius`].
+    #[builder(default = "RADIUS_SEARCH_LIMIT")]
+    radius_search_limit: usize,
+
+    /// Only update metadata when storing a node whose content is already stored.
+    ///
+    /// Node IDs are derived from a hash of the node's content, so a conflicting ID means the
+    /// chunk and vectors are unchanged. When enabled, such conflicts skip rewriting the chunk and
+    /// vectors and only update the metadata columns, and only if the metadata differs.
+    #[builder(default)]
+    dedup_update_metadata: bool,
+
+    /// Only update a conflicting row when its chunk changed.
+    ///
+    /// Adds `WHERE <table>.chunk IS DISTINCT FROM EXCLUDED.chunk` to the upsert's `DO UPDATE`
+    /// clause, so storing unchanged content neither rewrites the vectors nor bumps `updated_at`.
+    /// Has no effect together with `dedup_update_metadata`, which has its own condition.
+    #[builder(default)]
+    update_only_changed_chunk: bool,
+
+    /// How metadata values are serialized into the `meta_*` JSONB columns.
+    ///
+    /// By default values keep their native JSON types, so numbers and booleans can be filtered
+    /// and exported as such.
+    #[builder(default)]
+    metadata_serialization: MetadataSerialization,
+
+    /// How similarity retrieval handles a query without an embedding.
+    ///
+    /// Defaults to returning an error.
+    #[builder(default)]
+    no_embedding_behavior: NoEmbeddingBehavior,
+
+    /// Create the table as `UNLOGGED`.
+    ///
+    /// Unlogged tables skip the write-ahead log, making writes considerably faster. The data is
+    /// not crash-safe: the table is truncated after a crash or unclean shutdown, and it is not
+    /// replicated. Only use this for throwaway stores such as caches or test fixtures.
+    #[builder(default)]
+    unlogged: bool,
+
+    /// Type of the vector index created during `setup`.
+    ///
+    /// Defaults to HNSW. For IVFFlat without `lists`, the number of lists is suggested from the
+    /// current row count (see [`PgVector::suggest_ivfflat_lists`]).
+    #[builder(default)]
+    index_type: VectorIndexType,
+
+    /// Compression method for the vector columns, applied during `setup`.
+    ///
+    /// Column compression requires `PostgreSQL` 14 or later; on older servers it is skipped with
+    /// a warning. Only values large enough to be TOASTed are compressed.
+    #[builder(default)]
+    vector_compression: Option<VectorCompression>,
+
+    /// Defer checking of deferrable constraints until the store transaction commits.
+    ///
+    /// Issues `SET CONSTRAINTS ALL DEFERRED` in each store transaction. Only constraints declared
+    /// `DEFERRABLE` are affected; a violation then fails the commit and rolls back the batch.
+    #[builder(default)]
+    defer_constraints: bool,
+
+    /// Minimum pgvector extension version required by `setup`.
+    ///
+    /// When set, `setup` fails with a descriptive error if the installed extension does not
+    /// satisfy the requirement.
+    #[builder(default)]
+    require_pgvector_version: Option<semver::VersionReq>,
+
+    /// Ingestion run ID used to tag rows stored by `store_nodes`.
+    ///
+    /// Set with [`PgVectorBuilder::with_run_id`], which also adds the `run_id` column.
+    #[builder(setter(custom), default)]
+    run_id: Option<Uuid>,
+
+    /// Name and version of the embedding model used to tag rows stored by this instance.
+    ///
+    /// Set with [`PgVectorBuilder::with_model`], which also adds the `model` column.
+    #[builder(setter(custom), default)]
+    model: Option<String>,
+
     /// Database connection URL.
     db_url: String,
 
+    /// Fallback database URLs, tried in order when `db_url` cannot be connected to.
+    ///
+    /// Each URL is retried up to `db_max_retry` times before moving on to the next one.
+    #[builder(default)]
+    db_fallback_urls: Vec<String>,
+
+    /// Create the database if it does not exist when connecting.
+    ///
+    /// When connecting fails because the database is missing, connects to the `postgres`
+    /// maintenance database with the same credentials and runs `CREATE DATABASE`. The user needs
+    /// the `CREATEDB` privilege. Intended for local development and CI, so it is off by default.
+    #[builder(default)]
+    create_database_if_missing: bool,
+
     /// Maximum connections allowed in the connection pool.
     #[builder(default = "DB_POOL_CONN_MAX")]
     db_max_connections: u32,
@@ -89,6 +248,36 @@ pub struct PgVector {
     #[builder(default = "Duration::from_secs(DB_POOL_CONN_RETRY_DELAY_SECS)")]
     db_conn_retry_delay: Duration,
 
+    /// Capacity of the prepared statement cache of each connection.
+    ///
+    /// Defaults to the sqlx default. Set to `0` to disable statement caching, e.g. behind
+    /// `PgBouncer` in transaction pooling mode.
+    #[builder(default)]
+    statement_cache_capacity: Option<usize>,
+
+    /// Maximum attempts of `setup` when it fails with a transient connection error.
+    ///
+    /// Separate from `db_max_retry`, which applies to each connection attempt. This lets
+    /// `setup` wait for a freshly started database to become ready.
+    #[builder(default = "SETUP_MAX_RETRIES")]
+    setup_max_retries: u32,
+
+    /// Delay before the first `setup` retry, doubled after every retry.
+    #[builder(default = "Duration::from_millis(SETUP_RETRY_BACKOFF_MILLIS)")]
+    setup_retry_backoff: Duration,
+
+    /// Maximum attempts of a retrieve that fails with a serialization failure (SQLSTATE `40001`).
+    #[builder(default = "RETRIEVE_MAX_RETRIES")]
+    retrieve_max_retries: u32,
+
+    /// Delay between retrieve attempts after a serialization failure.
+    #[builder(default = "Duration::from_millis(RETRIEVE_RETRY_DELAY_MILLIS)")]
+    retrieve_retry_delay: Duration,
+
+    /// Maximum reconnect attempts per page when a scan hits a transient connection error.
+    #[builder(default = "SCAN_MAX_RECONNECTS")]
+    scan_max_reconnects: u32,
+
     /// Lazy-initialized database connection pool.
     #[builder(default = "Arc::new(OnceLock::new())")]
     connection_pool: Arc<OnceLock<PgPool>>,
@@ -177,6 +366,106 @@ impl PgVectorBuilder {
         self
     }
 
+    /// Enables full-text search over the chunk.
+    ///
+    /// Adds a `tsvector` column computed from the chunk at insert time, indexed with GIN. If the
+    /// configuration reads the language from metadata, a `language` column is added as well and
+    /// used as the text search configuration for each row.
+    ///
+    /// # Arguments
+    ///
+    /// * `config` - The full-text configuration, or the default language as a string.
+    ///
+    /// # Returns
+    ///
+    /// * Returns a mutable reference to `self` for method chaining.
+    pub fn with_fulltext_search(&mut self, config: impl Into<FullTextConfig>) -> &mut Self {
+        let config = config.into();
+        let fields = self.fields.get_or_insert_with(Self::default_fields);
+
+        if config.language_metadata_key.is_some() {
+            fields.push(FieldConfig::Language(config.clone()));
+        }
+        fields.push(FieldConfig::FullText(config));
+
+        self
+    }
+
+    /// Tags every stored row with an ingestion run ID.
+    ///
+    /// Adds a `run_id` column to the table. Rows stored through `store_nodes` are tagged with
+    /// `run_id`, which allows rolling back a run with [`PgVector::delete_run`]. Individual store
+    /// operations can use a different run ID with [`PgVector::store_nodes_with_run_id`].
+    ///
+    /// # Arguments
+    ///
+    /// * `run_id` - The default run ID for stored rows.
+    ///
+    /// # Returns
+    ///
+    /// * Returns a mutable reference to `self` for method chaining.
+    pub fn with_run_id(&mut self, run_id: impl Into<Uuid>) -> &mut Self {
+        self.run_id = Some(Some(run_id.into()));
+
+        let fields = self.fields.get_or_insert_with(Self::default_fields);
+        if !fields
+            .iter()
+            .any(|field| matches!(field, FieldConfig::RunId))
+        {
+            fields.push(FieldConfig::RunId);
+        }
+
+        self
+    }
+
+    /// Tags every stored row with the embedding model that produced its vectors.
+    ///
+    /// Adds a `model` column to the table. When mixing embeddings from different model
+    /// versions, retrieval can be restricted to a compatible set with a `model = "<name>"`
+    /// filter.
+    ///
+    /// # Arguments
+    ///
+    /// * `model` - The model name and version, e.g. `text-embedding-3-small@2024-01`.
+    ///
+    /// # Returns
+    ///
+    /// * Returns a mutable reference to `self` for method chaining.
+    pub fn with_model(&mut self, model: impl Into<String>) -> &mut Self {
+        self.model = Some(Some(model.into()));
+
+        let fields = self.fields.get_or_insert_with(Self::default_fields);
+        if !fields
+            .iter()
+            .any(|field| matches!(field, FieldConfig::Model))
+        {
+            fields.push(FieldConfig::Model);
+        }
+
+        self
+    }
+
+    /// Adds `created_at` and `updated_at` audit columns to the table.
+    ///
+    /// `created_at` is set when a row is first inserted, `updated_at` every time the row is
+    /// stored. Both are `TIMESTAMPTZ` columns maintained by the database.
+    ///
+    /// # Returns
+    ///
+    /// * Returns a mutable reference to `self` for method chaining.
+    pub fn with_audit_columns(&mut self) -> &mut Self {
+        let fields = self.fields.get_or_insert_with(Self::default_fields);
+        if !fields
+            .iter()
+            .any(|field| matches!(field, FieldConfig::CreatedAt))
+        {
+            fields.push(FieldConfig::CreatedAt);
+            fields.push(FieldConfig::UpdatedAt);
+        }
+
+        self
+    }
+
     fn default_fields() -> Vec<FieldConfig> {
         vec![FieldConfig::ID, FieldConfig::Chunk]
     }
@@ -1,7 +1,7 @@
 //! Storage persistence implementation for vector embeddings.
 //!
 //! Implements the [`Persist`] trait for [`PgVector`], providing vector storage capabilities:
-//! - Database schema initialization and setup
+//! - Database schema initialization and setup, retried while the database becomes ready
 //! - Single-node storage operations
 //! - Optimized batch storage with configurable batch sizes
 //!
@@ -14,13 +14,49 @@ use swiftide_core::{
     indexing::{IndexingStream, Node},
     Persist,
 };
+use tokio::time::sleep;
 
-#[async_trait]
-impl Persist for PgVector {
-    #[tracing::instrument(skip_all)]
-    async fn setup(&self) -> Result<()> {
+impl PgVector {
+    /// Runs `setup`, retrying with exponential backoff while the database is unavailable.
+    async fn setup_with_retry(&self) -> Result<()> {
+        let mut backoff = self.setup_retry_backoff;
+        let mut attempt = 1;
+
+        loop {
+            match self.try_setup().await {
+                Ok(()) => return Ok(()),
+                Err(err) if attempt < self.setup_max_retries && Self::is_transient_error(&err) => {
+                    tracing::warn!(
+                        error = %err,
+                        attempt = attempt,
+                        max_retries = self.setup_max_retries,
+                        backoff_ms = backoff.as_millis(),
+                        "Database not ready for setup, retrying..."
+                    );
+                    sleep(backoff).await;
+                    backoff = backoff.saturating_mul(2);
+                    attempt += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Returns true if any cause of the error is a transient connection error.
+    fn is_transient_error(err: &anyhow::Error) -> bool {
+        err.chain().any(|cause| {
+            cause
+                .downcast_ref::<sqlx::Error>()
+                .is_some_and(Self::is_transient_connection_error)
+        })
+    }
+
+    async fn try_setup(&self) -> Result<()> {
         // Get or initialize the connection pool
-        let pool = self.pool_get_or_initialize().await?;
+        self.pool_get_or_initialize().await?;
+
+        // Create extension and verify its version
+        self.create_extension().await?;
 
         if self.sql_stmt_bulk_insert.get().is_none() {
             let sql = self.generate_unnest_upsert_sql()?;
@@ -30,23 +66,21 @@ impl Persist for PgVector {
                 .map_err(|_| anyhow!("SQL bulk store statement is already set"))?;
         }
 
-        let mut tx = pool.begin().await?;
-
-        // Create extension
-        let sql = "CREATE EXTENSION IF NOT EXISTS vector";
-        sqlx::query(sql).execute(&mut *tx).await?;
-
-        // Create table
-        let create_table_sql = self.generate_create_table_sql()?;
-        sqlx::query(&create_table_sql).execute(&mut *tx).await?;
-
-        // Create HNSW index
-        let index_sql = self.create_index_sql()?;
-        sqlx::query(&index_sql).execute(&mut *tx).await?;
+        // With an inferred vector size, the schema is created on the first store
+        if self.awaiting_inferred_vector_size() {
+            tracing::debug!("Deferring schema creation until the vector size is inferred");
+            return Ok(());
+        }
 
-        tx.commit().await?;
+        self.create_schema().await
+    }
+}
 
-        Ok(())
+#[async_trait]
+impl Persist for PgVector {
+    #[tracing::instrument(skip_all)]
+    async fn setup(&self) -> Result<()> {
+        self.setup_with_retry().await
     }
 
     #[tracing::instrument(skip_all)]
@@ -71,9 +105,19 @@ impl Persist for PgVector {
 
 #[cfg(test)]
 mod tests {
-    use crate::pgvector::fixtures::TestContext;
+    use crate::pgvector::{
+        fixtures::TestContext, PgVector, PgVectorBuilder, VectorCompression, VectorSize,
+    };
+    use futures_util::TryStreamExt;
+    use sqlx::postgres::PgConnectOptions;
     use std::collections::HashSet;
-    use swiftide_core::{indexing::EmbeddedField, Persist};
+    use std::{str::FromStr, time::Duration};
+    use swiftide_core::{
+        indexing::{self, EmbeddedField},
+        querying::{search_strategies::SimilaritySingleEmbedding, states, Query},
+        Persist, Retrieve,
+    };
+    use tokio::net::{TcpListener, TcpStream};
 
     #[test_log::test(tokio::test)]
     async fn test_persist_setup_no_error_when_table_exists() {
@@ -90,4 +134,603 @@ mod tests {
             .await
             .expect("PgVector setup should not fail when the table already exists");
     }
+
+    #[test_log::test(tokio::test)]
+    async fn test_delete_run_only_removes_that_run() {
+        let run_a = sqlx::types::Uuid::new_v4();
+        let run_b = sqlx::types::Uuid::new_v4();
+
+        let test_context = TestContext::setup_with_builder(
+            None,
+            HashSet::from([EmbeddedField::Combined]),
+            |builder| builder.with_run_id(run_a),
+        )
+        .await
+        .expect("Test setup failed");
+
+        let node = |chunk: &str| {
+            indexing::Node::new(chunk)
+                .with_vectors([(EmbeddedField::Combined, vec![1.0; 384])])
+                .to_owned()
+        };
+
+        // Uses the configured run ID
+        test_context
+            .pgv_storage
+            .store_nodes(&[node("run_a_1"), node("run_a_2")])
+            .await
+            .unwrap();
+
+        test_context
+            .pgv_storage
+            .store_nodes_with_run_id(&[node("run_b_1")], Some(run_b))
+            .await
+            .unwrap();
+
+        let deleted = test_context.pgv_storage.delete_run(run_a).await.unwrap();
+        assert_eq!(deleted, 2);
+
+        let pool = test_context.pgv_storage.get_pool().await.unwrap();
+        let remaining: Vec<(String, sqlx::types::Uuid)> =
+            sqlx::query_as("SELECT chunk, run_id FROM swiftide_pgvector_test")
+                .fetch_all(pool)
+                .await
+                .unwrap();
+
+        assert_eq!(remaining, vec![("run_b_1".to_string(), run_b)]);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_setup_waits_for_database() {
+        let test_context =
+            TestContext::setup_with_cfg(None, HashSet::from([EmbeddedField::Combined]))
+                .await
+                .expect("Test setup failed");
+
+        let db_url = test_context.pgv_storage.db_url.clone();
+        let db_port = PgConnectOptions::from_str(&db_url).unwrap().get_port();
+
+        // Reserve a port for a proxy to the database that only starts listening later
+        let proxy_port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let proxy_url = db_url.replace(&format!(":{db_port}/"), &format!(":{proxy_port}/"));
+
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+
+            let listener = TcpListener::bind(("127.0.0.1", proxy_port)).await.unwrap();
+            loop {
+                let (mut inbound, _) = listener.accept().await.unwrap();
+                tokio::spawn(async move {
+                    let mut outbound = TcpStream::connect(("127.0.0.1", db_port)).await.unwrap();
+                    let _ = tokio::io::copy_bidirectional(&mut inbound, &mut outbound).await;
+                });
+            }
+        });
+
+        let pgv = PgVector::builder()
+            .db_url(proxy_url)
+            .db_max_retry(1_u32)
+            .setup_max_retries(10_u32)
+            .setup_retry_backoff(Duration::from_millis(100))
+            .table_name("swiftide_pgvector_late")
+            .vector_size(384)
+            .with_vector(EmbeddedField::Combined)
+            .build()
+            .unwrap();
+
+        pgv.setup().await.unwrap();
+
+        let pool = test_context.pgv_storage.get_pool().await.unwrap();
+        let exists: bool =
+            sqlx::query_scalar("SELECT to_regclass('swiftide_pgvector_late') IS NOT NULL")
+                .fetch_one(pool)
+                .await
+                .unwrap();
+        assert!(exists);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_update_metadata_where_renames_value() {
+        let test_context = TestContext::setup_with_cfg(
+            vec!["source"].into(),
+            HashSet::from([EmbeddedField::Combined]),
+        )
+        .await
+        .expect("Test setup failed");
+
+        let nodes: Vec<_> = [("a", "old"), ("b", "old"), ("c", "other")]
+            .into_iter()
+            .map(|(chunk, source)| {
+                indexing::Node::new(chunk)
+                    .with_metadata(("source", source))
+                    .with_vectors([(EmbeddedField::Combined, vec![1.0; 384])])
+                    .to_owned()
+            })
+            .collect();
+
+        test_context.pgv_storage.store_nodes(&nodes).await.unwrap();
+
+        let updated = test_context
+            .pgv_storage
+            .update_metadata_where("source = \"old\"", "source", "new")
+            .await
+            .unwrap();
+        assert_eq!(updated, 2);
+
+        let pool = test_context.pgv_storage.get_pool().await.unwrap();
+        let sources: Vec<(String, String)> = sqlx::query_as(
+            "SELECT chunk, meta_source->>'source' FROM swiftide_pgvector_test ORDER BY chunk",
+        )
+        .fetch_all(pool)
+        .await
+        .unwrap();
+
+        assert_eq!(
+            sources,
+            vec![
+                ("a".to_string(), "new".to_string()),
+                ("b".to_string(), "new".to_string()),
+                ("c".to_string(), "other".to_string()),
+            ]
+        );
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_update_metadata_where_touches_updated_at() {
+        let test_context = TestContext::setup_with_builder(
+            vec!["source"].into(),
+            HashSet::from([EmbeddedField::Combined]),
+            PgVectorBuilder::with_audit_columns,
+        )
+        .await
+        .expect("Test setup failed");
+
+        let node = indexing::Node::new("a")
+            .with_metadata(("source", "old"))
+            .with_vectors([(EmbeddedField::Combined, vec![1.0; 384])])
+            .to_owned();
+        test_context.pgv_storage.store_nodes(&[node]).await.unwrap();
+
+        let pool = test_context.pgv_storage.get_pool().await.unwrap();
+        sqlx::query("UPDATE swiftide_pgvector_test SET updated_at = now() - interval '1 day'")
+            .execute(pool)
+            .await
+            .unwrap();
+
+        test_context
+            .pgv_storage
+            .update_metadata_where("source = \"old\"", "source", "new")
+            .await
+            .unwrap();
+
+        let touched: bool = sqlx::query_scalar(
+            "SELECT updated_at > now() - interval '1 hour' FROM swiftide_pgvector_test",
+        )
+        .fetch_one(pool)
+        .await
+        .unwrap();
+        assert!(touched, "updated_at should be set by the metadata update");
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_statement_cache_capacity() {
+        // Cached statements are prepared under a name and show up in `pg_prepared_statements`,
+        // uncached statements are prepared unnamed and do not
+        for (capacity, expect_cached) in [(0_usize, false), (16, true)] {
+            let test_context = TestContext::setup_with_builder(
+                None,
+                HashSet::from([EmbeddedField::Combined]),
+                |builder| builder.statement_cache_capacity(capacity),
+            )
+            .await
+            .expect("Test setup failed");
+
+            let pool = test_context.pgv_storage.get_pool().await.unwrap();
+            let mut conn = pool.acquire().await.unwrap();
+
+            for _ in 0..2 {
+                sqlx::query("SELECT COUNT(*) FROM swiftide_pgvector_test WHERE chunk = $1")
+                    .bind("cached")
+                    .execute(&mut *conn)
+                    .await
+                    .unwrap();
+            }
+
+            let prepared: i64 = sqlx::query_scalar(
+                "SELECT COUNT(*) FROM pg_prepared_statements \
+                 WHERE statement LIKE '%FROM swiftide_pgvector_test WHERE chunk%' \
+                 AND statement NOT LIKE '%pg_prepared_statements%'",
+            )
+            .fetch_one(&mut *conn)
+            .await
+            .unwrap();
+            assert_eq!(prepared > 0, expect_cached, "capacity {capacity}");
+        }
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_recent_returns_latest_stores_first() {
+        let test_context = TestContext::setup_with_builder(
+            None,
+            HashSet::from([EmbeddedField::Combined]),
+            PgVectorBuilder::with_audit_columns,
+        )
+        .await
+        .expect("Test setup failed");
+
+        let node = |chunk: &str| {
+            indexing::Node::new(chunk)
+                .with_vectors([(EmbeddedField::Combined, vec![1.0; 384])])
+                .to_owned()
+        };
+
+        for chunk in ["first", "second", "third"] {
+            test_context
+                .pgv_storage
+                .store_nodes(&[node(chunk)])
+                .await
+                .unwrap();
+        }
+
+        // Storing again bumps `updated_at`
+        test_context
+            .pgv_storage
+            .store_nodes(&[node("first")])
+            .await
+            .unwrap();
+
+        let recent = test_context.pgv_storage.recent(2).await.unwrap();
+        let chunks: Vec<_> = recent.iter().map(|node| node.chunk.as_str()).collect();
+        assert_eq!(chunks, ["first", "third"]);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_recent_requires_audit_columns() {
+        let test_context =
+            TestContext::setup_with_cfg(None, HashSet::from([EmbeddedField::Combined]))
+                .await
+                .expect("Test setup failed");
+
+        let err = test_context.pgv_storage.recent(1).await.unwrap_err();
+        assert!(err.to_string().contains("Audit columns are not configured"));
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_update_only_changed_chunk_keeps_updated_at() {
+        let test_context = TestContext::setup_with_builder(
+            None,
+            HashSet::from([EmbeddedField::Combined]),
+            |builder| builder.with_audit_columns().update_only_changed_chunk(true),
+        )
+        .await
+        .expect("Test setup failed");
+
+        let node = indexing::Node::new("unchanged")
+            .with_vectors([(EmbeddedField::Combined, vec![1.0; 384])])
+            .to_owned();
+
+        let pool = test_context.pgv_storage.get_pool().await.unwrap();
+        let sql = format!(
+            "SELECT updated_at::text FROM {}",
+            test_context.pgv_storage.table_name
+        );
+
+        test_context
+            .pgv_storage
+            .store_nodes(&[node.clone()])
+            .await
+            .unwrap();
+        let first: String = sqlx::query_scalar(&sql).fetch_one(pool).await.unwrap();
+
+        // `now()` is the transaction start time, so make sure a bump would be visible
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        test_context.pgv_storage.store_nodes(&[node]).await.unwrap();
+        let second: String = sqlx::query_scalar(&sql).fetch_one(pool).await.unwrap();
+
+        assert_eq!(first, second);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_dedup_update_metadata_keeps_vector() {
+        let test_context = TestContext::setup_with_builder(
+            vec!["tag"].into(),
+            HashSet::from([EmbeddedField::Combined]),
+            |builder| builder.dedup_update_metadata(true),
+        )
+        .await
+        .expect("Test setup failed");
+
+        let original = indexing::Node::new("same content")
+            .with_metadata(("tag", "old"))
+            .with_vectors([(EmbeddedField::Combined, vec![1.0; 384])])
+            .to_owned();
+
+        let retagged = indexing::Node::new("same content")
+            .with_metadata(("tag", "new"))
+            .with_vectors([(EmbeddedField::Combined, vec![2.0; 384])])
+            .to_owned();
+
+        assert_eq!(original.id(), retagged.id());
+
+        for node in [original.clone(), retagged] {
+            test_context
+                .pgv_storage
+                .batch_store(vec![node])
+                .await
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap();
+        }
+
+        let pool = test_context.pgv_storage.get_pool().await.unwrap();
+        let (tag, vector): (String, pgvector::Vector) = sqlx::query_as(
+            "SELECT meta_tag->>'tag', vector_combined FROM swiftide_pgvector_test WHERE id = $1",
+        )
+        .bind(original.id())
+        .fetch_one(pool)
+        .await
+        .unwrap();
+
+        assert_eq!(tag, "new", "Metadata should be updated");
+        assert_eq!(
+            vector.to_vec(),
+            vec![1.0; 384],
+            "Vector should not be rewritten"
+        );
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_auto_vector_size_creates_table_on_first_store() {
+        let test_context = TestContext::setup_with_builder(
+            None,
+            HashSet::from([EmbeddedField::Combined]),
+            |builder| builder.vector_size(VectorSize::Auto),
+        )
+        .await
+        .expect("Test setup failed");
+
+        let node = indexing::Node::new("auto sized")
+            .with_vectors([(EmbeddedField::Combined, vec![1.0; 8])])
+            .to_owned();
+
+        test_context
+            .pgv_storage
+            .batch_store(vec![node])
+            .await
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        let pool = test_context.pgv_storage.get_pool().await.unwrap();
+        let (column_type,): (String,) = sqlx::query_as(
+            "SELECT format_type(atttypid, atttypmod) FROM pg_attribute \
+             WHERE attrelid = 'swiftide_pgvector_test'::regclass AND attname = 'vector_combined'",
+        )
+        .fetch_one(pool)
+        .await
+        .unwrap();
+
+        assert_eq!(column_type, "vector(8)");
+        assert_eq!(test_context.pgv_storage.resolved_vector_size().unwrap(), 8);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_auto_vector_size_concurrent_first_stores() {
+        let test_context = TestContext::setup_with_builder(
+            None,
+            HashSet::from([EmbeddedField::Combined]),
+            |builder| builder.vector_size(VectorSize::Auto),
+        )
+        .await
+        .expect("Test setup failed");
+
+        let stores = (0..4).map(|i| {
+            let pgv_storage = test_context.pgv_storage.clone();
+            let node = indexing::Node::new(format!("concurrent {i}"))
+                .with_vectors([(EmbeddedField::Combined, vec![1.0; 8])])
+                .to_owned();
+
+            async move { pgv_storage.store_nodes(&[node]).await }
+        });
+
+        for result in futures_util::future::join_all(stores).await {
+            result.unwrap();
+        }
+
+        let pool = test_context.pgv_storage.get_pool().await.unwrap();
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM swiftide_pgvector_test")
+            .fetch_one(pool)
+            .await
+            .unwrap();
+        assert_eq!(count, 4);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_auto_vector_size_retries_failed_schema_creation() {
+        let test_context = TestContext::setup_with_builder(
+            None,
+            HashSet::from([EmbeddedField::Combined]),
+            |builder| builder.vector_size(VectorSize::Auto),
+        )
+        .await
+        .expect("Test setup failed");
+
+        // A view under the table name makes the first schema creation fail
+        let pool = test_context.pgv_storage.get_pool().await.unwrap();
+        sqlx::query("CREATE VIEW swiftide_pgvector_test AS SELECT 1 AS id")
+            .execute(pool)
+            .await
+            .unwrap();
+
+        let node = indexing::Node::new("retried")
+            .with_vectors([(EmbeddedField::Combined, vec![1.0; 8])])
+            .to_owned();
+
+        assert!(test_context
+            .pgv_storage
+            .store_nodes(&[node.clone()])
+            .await
+            .is_err());
+
+        sqlx::query("DROP VIEW swiftide_pgvector_test")
+            .execute(pool)
+            .await
+            .unwrap();
+
+        test_context.pgv_storage.store_nodes(&[node]).await.unwrap();
+
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM swiftide_pgvector_test")
+            .fetch_one(pool)
+            .await
+            .unwrap();
+        assert_eq!(count, 1);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_setup_fails_on_unsupported_pgvector_version() {
+        let result = TestContext::setup_with_builder(
+            None,
+            HashSet::from([EmbeddedField::Combined]),
+            |builder| {
+                builder.require_pgvector_version(semver::VersionReq::parse(">=99.0.0").unwrap())
+            },
+        )
+        .await;
+
+        let Err(err) = result else {
+            panic!("Setup should fail when the pgvector version requirement is not met");
+        };
+
+        assert!(
+            err.to_string()
+                .contains("does not satisfy the required version >=99.0.0"),
+            "Unexpected error: {err}"
+        );
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_defer_constraints_checks_at_commit() {
+        let test_context = TestContext::setup_with_builder(
+            None,
+            HashSet::from([EmbeddedField::Combined]),
+            |builder| builder.defer_constraints(true),
+        )
+        .await
+        .expect("Test setup failed");
+
+        let pool = test_context.pgv_storage.get_pool().await.unwrap();
+        sqlx::query(
+            "ALTER TABLE swiftide_pgvector_test ADD CONSTRAINT unique_chunk \
+             UNIQUE (chunk) DEFERRABLE INITIALLY IMMEDIATE",
+        )
+        .execute(pool)
+        .await
+        .unwrap();
+
+        let node = |path: &str, chunk: &str| {
+            let mut node = indexing::Node::builder()
+                .path(path)
+                .chunk(chunk)
+                .build()
+                .unwrap();
+            node.with_vectors([(EmbeddedField::Combined, vec![1.0; 384])]);
+            node
+        };
+
+        // Same chunk under different paths violates the deferred constraint at commit
+        let err = test_context
+            .pgv_storage
+            .store_nodes(&[node("a", "duplicate"), node("b", "duplicate")])
+            .await
+            .expect_err("Violating batch should fail");
+        assert!(
+            err.to_string().contains("Failed to commit transaction"),
+            "Constraint should be checked at commit, got: {err}"
+        );
+
+        test_context
+            .pgv_storage
+            .store_nodes(&[node("a", "first"), node("b", "second")])
+            .await
+            .expect("Valid batch should commit");
+
+        let (count,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM swiftide_pgvector_test")
+            .fetch_one(pool)
+            .await
+            .unwrap();
+        assert_eq!(count, 2, "Only the valid batch should be stored");
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_vector_compression_round_trip() {
+        let test_context = TestContext::setup_with_builder(
+            None,
+            HashSet::from([EmbeddedField::Combined]),
+            |builder| builder.vector_compression(VectorCompression::Lz4),
+        )
+        .await
+        .expect("Test setup failed");
+
+        let pool = test_context.pgv_storage.get_pool().await.unwrap();
+        let (compression,): (String,) = sqlx::query_as(
+            "SELECT attcompression::text FROM pg_attribute \
+             WHERE attrelid = 'swiftide_pgvector_test'::regclass AND attname = 'vector_combined'",
+        )
+        .fetch_one(pool)
+        .await
+        .unwrap();
+        assert_eq!(compression, "l", "Vector column should use lz4 compression");
+
+        let node = indexing::Node::new("compressed")
+            .with_vectors([(EmbeddedField::Combined, vec![0.5; 384])])
+            .to_owned();
+        test_context.pgv_storage.store_nodes(&[node]).await.unwrap();
+
+        let (vector,): (pgvector::Vector,) =
+            sqlx::query_as("SELECT vector_combined FROM swiftide_pgvector_test")
+                .fetch_one(pool)
+                .await
+                .unwrap();
+        assert_eq!(vector.to_vec(), vec![0.5; 384]);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_unlogged_table_round_trip() {
+        let test_context = TestContext::setup_with_builder(
+            None,
+            HashSet::from([EmbeddedField::Combined]),
+            |builder| builder.unlogged(true),
+        )
+        .await
+        .expect("Test setup failed");
+
+        let pool = test_context.pgv_storage.get_pool().await.unwrap();
+        let (persistence,): (String,) = sqlx::query_as(
+            "SELECT relpersistence::text FROM pg_class WHERE oid = 'swiftide_pgvector_test'::regclass",
+        )
+        .fetch_one(pool)
+        .await
+        .unwrap();
+        assert_eq!(persistence, "u", "Table should be unlogged");
+
+        let node = indexing::Node::new("ephemeral")
+            .with_vectors([(EmbeddedField::Combined, vec![1.0; 384])])
+            .to_owned();
+        test_context.pgv_storage.store_nodes(&[node]).await.unwrap();
+
+        let mut query = Query::<states::Pending>::new("test_query");
+        query.embedding = Some(vec![1.0; 384]);
+
+        let result = test_context
+            .pgv_storage
+            .retrieve(&SimilaritySingleEmbedding::<()>::default(), query)
+            .await
+            .unwrap();
+        assert_eq!(result.documents(), ["ephemeral".to_string()]);
+    }
 }
@@ -0,0 +1,481 @@
+//! Index quality and maintenance utilities for vector storage.
+//!
+//! Provides helpers to build, inspect and validate the vector index:
+//! - Index creation with IVFFlat `lists` suggested from the row count
+//! - Online switching between index types
+//! - Recall measurement of approximate (indexed) search against an exact scan
+//!
+//! All helpers operate on the table and vector column configured on [`PgVector`].
+use crate::pgvector::{PgVector, VectorIndexType};
+use anyhow::{anyhow, Result};
+use pgvector::Vector;
+use sqlx::{types::Uuid, PgPool};
+use std::collections::HashSet;
+
+/// Row count up to which IVFFlat lists are suggested as `rows / 1000`.
+const IVFFLAT_LINEAR_LISTS_MAX_ROWS: i64 = 1_000_000;
+
+impl PgVector {
+    /// Creates the configured vector index if it does not exist.
+    ///
+    /// For an IVFFlat index without `lists`, the number of lists is suggested from the current
+    /// row count with [`PgVector::suggest_ivfflat_lists`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an existing vector column's dimension differs from the configured
+    /// vector size, the row count cannot be read, the index SQL cannot be generated, or the index
+    /// creation fails.
+    pub async fn build_index(&self) -> Result<()> {
+        let pool = self.pool_get_or_initialize().await?;
+        self.validate_vector_dimensions(&mut *pool.acquire().await?)
+            .await?;
+
+        let index_type = self.resolve_index_type(self.index_type).await?;
+        let sql = self.create_index_sql_for(index_type)?;
+
+        sqlx::query(&sql).execute(pool).await?;
+
+        Ok(())
+    }
+
+    /// Switches the vector index to another index type without dropping data.
+    ///
+    /// The new index is built with `CREATE INDEX CONCURRENTLY`, so reads and writes continue
+    /// while it builds. Once the new index is valid, the index of the other type is dropped
+    /// concurrently. If the build fails or leaves an invalid index, the new index is dropped and
+    /// the old one is kept.
+    ///
+    /// Switching to the current type with different parameters, such as another number of
+    /// IVFFlat `lists`, rebuilds the index the same way under a temporary name. Once it is
+    /// valid, the old index is dropped and the new one renamed in a single transaction, which
+    /// briefly locks the table.
+    ///
+    /// The configured `index_type` is not changed; update it accordingly, otherwise the next
+    /// `setup` recreates the configured index.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the index SQL cannot be generated, the build fails, or the new index
+    /// is invalid.
+    pub async fn switch_index_type(&self, new_type: VectorIndexType) -> Result<()> {
+        let new_type = self.resolve_index_type(new_type).await?;
+        let new_index = self.index_name(new_type);
+
+        // An existing index of the same type with other parameters is rebuilt next to it
+        let rebuild = self
+            .index_options(&new_index)
+            .await?
+            .is_some_and(|options| options != Self::expected_index_options(new_type));
+        let build_index = if rebuild {
+            let build_index = format!("{new_index}_rebuild");
+            self.drop_index_concurrently(&build_index).await?;
+            build_index
+        } else {
+            new_index.clone()
+        };
+
+        let create_sql =
+            self.generate_named_index_sql(new_type, &build_index, self.index_column()?, true)?;
+
+        let pool = self.pool_get_or_initialize().await?;
+
+        // Concurrent index builds cannot run inside a transaction
+        if let Err(err) = sqlx::query(&create_sql).execute(pool).await {
+            self.drop_index_concurrently(&build_index).await?;
+            return Err(anyhow!(err).context("Failed to build new vector index"));
+        }
+
+        let (is_valid,): (bool,) =
+            sqlx::query_as("SELECT indisvalid FROM pg_index WHERE indexrelid = $1::regclass")
+                .bind(&build_index)
+                .fetch_one(pool)
+                .await?;
+
+        if !is_valid {
+            self.drop_index_concurrently(&build_index).await?;
+            return Err(anyhow!("New vector index {build_index} is invalid"));
+        }
+
+        if rebuild {
+            // Swap atomically, so the index is always available under its expected name
+            let mut tx = pool.begin().await?;
+            sqlx::query(&format!("DROP INDEX {new_index}"))
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query(&format!("ALTER INDEX {build_index} RENAME TO {new_index}"))
+                .execute(&mut *tx)
+                .await?;
+            tx.commit().await?;
+        }
+
+        let old_types = [
+            VectorIndexType::Hnsw,
+            VectorIndexType::IvfFlat { lists: None },
+        ];
+        for old_index in old_types
+            .into_iter()
+            .map(|index_type| self.index_name(index_type))
+            .filter(|name| *name != new_index)
+        {
+            self.drop_index_concurrently(&old_index).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the storage parameters of an index, such as `lists=100`, or `None` if the index
+    /// does not exist.
+    async fn index_options(&self, index_name: &str) -> Result<Option<Vec<String>>> {
+        let pool = self.pool_get_or_initialize().await?;
+
+        Ok(sqlx::query_scalar(
+            "SELECT COALESCE(reloptions, '{}') FROM pg_class WHERE oid = to_regclass($1)",
+        )
+        .bind(index_name)
+        .fetch_optional(pool)
+        .await?)
+    }
+
+    /// Returns the storage parameters an index of the given type is created with.
+    fn expected_index_options(index_type: VectorIndexType) -> Vec<String> {
+        match index_type {
+            VectorIndexType::Hnsw | VectorIndexType::IvfFlat { lists: None } => Vec::new(),
+            VectorIndexType::IvfFlat { lists: Some(lists) } => vec![format!("lists={lists}")],
+        }
+    }
+
+    async fn drop_index_concurrently(&self, index_name: &str) -> Result<()> {
+        if !Self::is_valid_identifier(index_name) {
+            return Err(anyhow!("Invalid index name"));
+        }
+
+        let pool = self.pool_get_or_initialize().await?;
+        sqlx::query(&format!("DROP INDEX CONCURRENTLY IF EXISTS {index_name}"))
+            .execute(pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Suggests the number of IVFFlat lists for the current row count.
+    ///
+    /// Follows the pgvector guideline of `rows / 1000` lists up to 1M rows and `sqrt(rows)`
+    /// beyond that, with a minimum of one list.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the row count cannot be read.
+    pub async fn suggest_ivfflat_lists(&self) -> Result<i32> {
+        let pool = self.pool_get_or_initialize().await?;
+
+        let sql = format!("SELECT COUNT(*) FROM {}", self.table_name);
+        let (rows,): (i64,) = sqlx::query_as(&sql).fetch_one(pool).await?;
+
+        Ok(Self::ivfflat_lists_for_rows(rows))
+    }
+
+    /// Resolves an IVFFlat index without `lists` to one with suggested lists.
+    pub(crate) async fn resolve_index_type(
+        &self,
+        index_type: VectorIndexType,
+    ) -> Result<VectorIndexType> {
+        match index_type {
+            VectorIndexType::IvfFlat { lists: None } => Ok(VectorIndexType::IvfFlat {
+                lists: Some(self.suggest_ivfflat_lists().await?),
+            }),
+            index_type => Ok(index_type),
+        }
+    }
+
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_precision_loss,
+        clippy::cast_sign_loss
+    )]
+    pub(crate) fn ivfflat_lists_for_rows(rows: i64) -> i32 {
+        let lists = if rows <= IVFFLAT_LINEAR_LISTS_MAX_ROWS {
+            rows / 1000
+        } else {
+            (rows as f64).sqrt() as i64
+        };
+
+        i32::try_from(lists.max(1)).unwrap_or(i32::MAX)
+    }
+
+    /// Measures the mean recall@k of the approximate (indexed) search against an exact scan.
+    ///
+    /// Up to `sample_queries` stored vectors are sampled at random and used as queries. For each
+    /// sample the top `k` results are fetched twice: once with sequential scans disabled, forcing
+    /// the index, and once with index scans disabled, forcing an exact brute-force scan. The
+    /// recall of a sample is the fraction of exact results also returned by the index. Samples
+    /// without exact results are not scored.
+    ///
+    /// # Arguments
+    ///
+    /// * `sample_queries` - Maximum number of stored vectors to sample as queries.
+    /// * `k` - Number of nearest neighbors to compare per query.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(f64)` - The mean recall@k across all scored samples, between `0.0` and `1.0`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - `sample_queries` or `k` is zero.
+    /// - No vector field is configured, the table contains no vectors, or no sample was scored.
+    /// - Any of the queries fail to execute.
+    #[allow(clippy::cast_precision_loss)]
+    pub async fn measure_recall(&self, sample_queries: usize, k: usize) -> Result<f64> {
+        if sample_queries == 0 || k == 0 {
+            return Err(anyhow!("sample_queries and k must be greater than zero"));
+        }
+
+        let vector_column_name = self.get_vector_column_name()?;
+        let pool = self.pool_get_or_initialize().await?;
+
+        let sample_limit = i64::try_from(sample_queries)
+            .map_err(|_| anyhow!("Failed to convert sample_queries to i64"))?;
+        let top_k = i64::try_from(k).map_err(|_| anyhow!("Failed to convert k to i64"))?;
+
+        let sample_sql = format!(
+            "SELECT {vector_column_name} FROM {} WHERE {vector_column_name} IS NOT NULL ORDER BY random() LIMIT $1",
+            self.table_name
+        );
+
+        let samples: Vec<(Vector,)> = sqlx::query_as(&sample_sql)
+            .bind(sample_limit)
+            .fetch_all(pool)
+            .await?;
+
+        if samples.is_empty() {
+            return Err(anyhow!("Cannot measure recall on a table without vectors"));
+        }
+
+        let search_sql = format!(
+            "SELECT id FROM {} ORDER BY {vector_column_name} <=> $1 LIMIT $2",
+            self.table_name
+        );
+
+        let mut total_recall = 0.0;
+        let mut scored = 0_usize;
+        for (embedding,) in &samples {
+            let approximate =
+                Self::search_ids_without(pool, &search_sql, embedding, top_k, "enable_seqscan")
+                    .await?;
+            let exact =
+                Self::search_ids_without(pool, &search_sql, embedding, top_k, "enable_indexscan")
+                    .await?;
+
+            if exact.is_empty() {
+                continue;
+            }
+
+            let hits = exact.intersection(&approximate).count();
+            total_recall += hits as f64 / exact.len() as f64;
+            scored += 1;
+        }
+
+        if scored == 0 {
+            return Err(anyhow!(
+                "Cannot measure recall: no sample returned exact results"
+            ));
+        }
+
+        Ok(total_recall / scored as f64)
+    }
+
+    /// Runs a nearest neighbor search with the given planner setting disabled for the
+    /// duration of a transaction, returning the matched IDs.
+    async fn search_ids_without(
+        pool: &PgPool,
+        sql: &str,
+        embedding: &Vector,
+        top_k: i64,
+        planner_setting: &str,
+    ) -> Result<HashSet<Uuid>> {
+        let mut tx = pool.begin().await?;
+
+        sqlx::query(&format!("SET LOCAL {planner_setting} = off"))
+            .execute(&mut *tx)
+            .await?;
+
+        let ids: Vec<(Uuid,)> = sqlx::query_as(sql)
+            .bind(embedding.clone())
+            .bind(top_k)
+            .fetch_all(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(ids.into_iter().map(|(id,)| id).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pgvector::{fixtures::TestContext, PgVector, VectorIndexType};
+    use futures_util::TryStreamExt;
+    use std::collections::HashSet;
+    use swiftide_core::{
+        indexing,
+        indexing::EmbeddedField,
+        querying::{search_strategies::SimilaritySingleEmbedding, states, Query},
+        Persist, Retrieve,
+    };
+
+    #[test]
+    fn test_ivfflat_lists_scale_with_row_count() {
+        assert_eq!(PgVector::ivfflat_lists_for_rows(0), 1);
+        assert_eq!(PgVector::ivfflat_lists_for_rows(10_000), 10);
+        assert_eq!(PgVector::ivfflat_lists_for_rows(500_000), 500);
+        assert_eq!(PgVector::ivfflat_lists_for_rows(1_000_000), 1000);
+        assert_eq!(PgVector::ivfflat_lists_for_rows(4_000_000), 2000);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_measure_recall_is_exact_on_tiny_table() {
+        let test_context = TestContext::setup_with_cfg(
+            vec!["filter"].into(),
+            HashSet::from([EmbeddedField::Combined]),
+        )
+        .await
+        .expect("Test setup failed");
+
+        let nodes = vec![
+            indexing::Node::new("recall_1").with_metadata(("filter", "a")),
+            indexing::Node::new("recall_2").with_metadata(("filter", "b")),
+            indexing::Node::new("recall_3").with_metadata(("filter", "c")),
+        ]
+        .into_iter()
+        .zip([1.0, 2.0, 4.0])
+        .map(|(node, first)| {
+            // Vary a single dimension so all vectors point in distinct directions
+            let mut vector = vec![1.0; 384];
+            vector[0] = first;
+            node.with_vectors([(EmbeddedField::Combined, vector)]);
+            node.to_owned()
+        })
+        .collect();
+
+        test_context
+            .pgv_storage
+            .batch_store(nodes)
+            .await
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        let recall = test_context
+            .pgv_storage
+            .measure_recall(3, 2)
+            .await
+            .expect("Measuring recall should succeed");
+
+        assert!(
+            (recall - 1.0).abs() < f64::EPSILON,
+            "Recall on a tiny table should be exact, got {recall}"
+        );
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_switch_index_type_from_hnsw_to_ivfflat() {
+        let test_context = TestContext::setup_with_cfg(
+            vec!["filter"].into(),
+            HashSet::from([EmbeddedField::Combined]),
+        )
+        .await
+        .expect("Test setup failed");
+
+        let nodes = (0..10)
+            .map(|i| {
+                indexing::Node::new(format!("switch_{i}"))
+                    .with_metadata(("filter", "true"))
+                    .with_vectors([(EmbeddedField::Combined, vec![1.0; 384])])
+                    .to_owned()
+            })
+            .collect();
+
+        test_context
+            .pgv_storage
+            .batch_store(nodes)
+            .await
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        test_context
+            .pgv_storage
+            .switch_index_type(VectorIndexType::IvfFlat { lists: Some(1) })
+            .await
+            .expect("Switching index type should succeed");
+
+        let pool = test_context.pgv_storage.get_pool().await.unwrap();
+        let indexes: Vec<(String,)> = sqlx::query_as(
+            "SELECT indexname FROM pg_indexes WHERE tablename = 'swiftide_pgvector_test' \
+             AND indexname LIKE '%embedding%' ORDER BY indexname",
+        )
+        .fetch_all(pool)
+        .await
+        .unwrap();
+        assert_eq!(
+            indexes,
+            vec![("swiftide_pgvector_test_embedding_ivfflat_idx".to_string(),)]
+        );
+
+        let mut query = Query::<states::Pending>::new("test_query");
+        query.embedding = Some(vec![1.0; 384]);
+
+        let mut search_strategy = SimilaritySingleEmbedding::<()>::default();
+        search_strategy.with_top_k(5);
+
+        let result = test_context
+            .pgv_storage
+            .retrieve(&search_strategy, query)
+            .await
+            .unwrap();
+        assert_eq!(result.documents().len(), 5);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_switch_index_type_rebuilds_with_new_lists() {
+        let test_context = TestContext::setup_with_builder(
+            None,
+            HashSet::from([EmbeddedField::Combined]),
+            |builder| builder.index_type(VectorIndexType::IvfFlat { lists: Some(1) }),
+        )
+        .await
+        .expect("Test setup failed");
+
+        let pool = test_context.pgv_storage.get_pool().await.unwrap();
+        let indexdefs = || async move {
+            sqlx::query_scalar::<_, String>(
+                "SELECT indexdef FROM pg_indexes WHERE tablename = 'swiftide_pgvector_test' \
+                 AND indexname LIKE '%embedding%'",
+            )
+            .fetch_all(pool)
+            .await
+            .unwrap()
+        };
+
+        let before = indexdefs().await;
+        assert_eq!(before.len(), 1);
+        assert!(before[0].contains("lists='1'"), "{}", before[0]);
+
+        test_context
+            .pgv_storage
+            .switch_index_type(VectorIndexType::IvfFlat { lists: Some(4) })
+            .await
+            .unwrap();
+
+        let after = indexdefs().await;
+        assert_eq!(after.len(), 1);
+        assert!(after[0].contains("lists='4'"), "{}", after[0]);
+        assert!(
+            after[0].contains("swiftide_pgvector_test_embedding_ivfflat_idx "),
+            "{}",
+            after[0]
+        );
+    }
+}
@@ -0,0 +1,383 @@
+//! Helpers for migrating to a new embedding model without downtime.
+//!
+//! Switching to a model with different dimensions is done alongside the existing column:
+//! - Add a vector column for the new model with [`PgVector::add_vector_field`]
+//! - Embed the stored chunks into it with [`PgVector::backfill_vector_field`]
+//! - Cut retrieval over to it with [`PgVector::switch_ranking_field`], which indexes it first
+//!
+//! Until the cut-over, retrieval keeps ranking on the existing column.
+use crate::pgvector::{
+    pgv_table_types::{FieldConfig, VectorConfig},
+    PgVector,
+};
+use anyhow::{anyhow, Result};
+use pgvector::Vector;
+use sqlx::types::Uuid;
+use std::sync::{Arc, OnceLock};
+use swiftide_core::{indexing::EmbeddedField, EmbeddingModel};
+
+impl PgVector {
+    /// Adds a vector column for `embedded_field` to the existing table.
+    ///
+    /// Existing rows have `NULL` in the new column until they are backfilled. The returned store
+    /// includes the new field and stores it on upsert; retrieval still ranks on the current
+    /// column until [`PgVector::switch_ranking_field`] is used. The new column is not indexed
+    /// until then.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the field is already configured, the size is not positive, or the
+    /// column cannot be added.
+    pub async fn add_vector_field(
+        &self,
+        embedded_field: EmbeddedField,
+        vector_size: i32,
+    ) -> Result<Self> {
+        if vector_size <= 0 {
+            return Err(anyhow!("Vector size must be positive"));
+        }
+
+        let config = VectorConfig::new(&embedded_field).with_vector_size(vector_size);
+
+        if self
+            .fields
+            .iter()
+            .any(|field| field.field_name() == config.field)
+        {
+            return Err(anyhow!(
+                "Vector field {} is already configured",
+                config.field
+            ));
+        }
+
+        if !Self::is_valid_identifier(&self.table_name) || !Self::is_valid_identifier(&config.field)
+        {
+            return Err(anyhow!("Invalid table or field name"));
+        }
+
+        let sql = format!(
+            "ALTER TABLE {} ADD COLUMN IF NOT EXISTS {} VECTOR({vector_size})",
+            self.table_name, config.field
+        );
+
+        let pool = self.pool_get_or_initialize().await?;
+        sqlx::query(&sql).execute(pool).await?;
+
+        let mut store = self.clone();
+
+        // Keep ranking on the current column, which is implicit while it is the only one
+        if store.ranking_field.is_none() {
+            let mut current = self.fields.iter().filter_map(|field| match field {
+                FieldConfig::Vector(config) => Some(&config.embedded_field),
+                _ => None,
+            });
+            if let (Some(current), None) = (current.next(), current.next()) {
+                store.ranking_field = Some(current.clone());
+            }
+        }
+
+        store.fields.push(FieldConfig::Vector(config));
+
+        // The upsert statement of the original store does not include the new column
+        store.sql_stmt_bulk_insert = Arc::new(OnceLock::from(store.generate_unnest_upsert_sql()?));
+
+        Ok(store)
+    }
+
+    /// Embeds the chunks of all rows missing a vector for `embedded_field` and stores them.
+    ///
+    /// Rows are processed in batches of `batch_size`, so the backfill can run while the table is
+    /// in use and be resumed after an interruption.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(u64)` - The number of backfilled rows.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the field is not configured, embedding fails, returns a different
+    /// number of embeddings than chunks or embeddings of the wrong size, or a query fails.
+    pub async fn backfill_vector_field(
+        &self,
+        embedded_field: &EmbeddedField,
+        embed_model: &dyn EmbeddingModel,
+    ) -> Result<u64> {
+        let config = self
+            .fields
+            .iter()
+            .find_map(|field| match field {
+                FieldConfig::Vector(config) if &config.embedded_field == embedded_field => {
+                    Some(config)
+                }
+                _ => None,
+            })
+            .ok_or_else(|| anyhow!("Vector field for {embedded_field} is not configured"))?;
+        let column = config.field.as_str();
+        let vector_size = usize::try_from(self.vector_size_for(config)?)
+            .map_err(|_| anyhow!("Invalid vector size for `{column}`"))?;
+
+        let limit = i64::try_from(self.batch_size)
+            .map_err(|_| anyhow!("Failed to convert batch_size to i64"))?;
+
+        let select_sql = format!(
+            "SELECT id, chunk FROM {} WHERE {column} IS NULL ORDER BY id LIMIT $1",
+            self.table_name
+        );
+        let update_sql = format!(
+            "UPDATE {table} SET {column} = data.vector \
+             FROM UNNEST($1::UUID[], $2::VECTOR[]) AS data(id, vector) \
+             WHERE {table}.id = data.id",
+            table = self.table_name
+        );
+
+        let pool = self.pool_get_or_initialize().await?;
+        let mut backfilled = 0;
+
+        loop {
+            let rows: Vec<(Uuid, String)> = sqlx::query_as(&select_sql)
+                .bind(limit)
+                .fetch_all(pool)
+                .await?;
+
+            if rows.is_empty() {
+                return Ok(backfilled);
+            }
+
+            let (ids, chunks): (Vec<_>, Vec<_>) = rows.into_iter().unzip();
+            let embeddings = embed_model.embed(chunks).await?;
+
+            if embeddings.len() != ids.len() {
+                return Err(anyhow!(
+                    "Expected {} embeddings, got {}",
+                    ids.len(),
+                    embeddings.len()
+                ));
+            }
+
+            if let Some((id, embedding)) = ids
+                .iter()
+                .zip(&embeddings)
+                .find(|(_, embedding)| embedding.len() != vector_size)
+            {
+                return Err(anyhow!(
+                    "Vector field `{column}` expects {vector_size} dimensions, got {} for row {id}",
+                    embedding.len()
+                ));
+            }
+
+            let vectors: Vec<Vector> = embeddings.into_iter().map(Vector::from).collect();
+
+            backfilled += sqlx::query(&update_sql)
+                .bind(&ids)
+                .bind(&vectors)
+                .execute(pool)
+                .await?
+                .rows_affected();
+
+            tracing::debug!(backfilled, column, "Backfilled vector column batch");
+        }
+    }
+
+    /// Returns a store ranking similarity retrieval on the vector column of `embedded_field`.
+    ///
+    /// If the column has no index yet, an index of the configured `index_type` is built
+    /// concurrently first, so retrieval does not fall back to sequential scans.
+    ///
+    /// The returned store shares the connection pool, so it can be swapped in without
+    /// reconnecting. The original store keeps ranking on its current column.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the field is not configured or building the index fails.
+    pub async fn switch_ranking_field(&self, embedded_field: EmbeddedField) -> Result<Self> {
+        let column = self.vector_column(&embedded_field)?;
+
+        if !self.column_has_index(column).await? {
+            self.build_column_index(column).await?;
+        }
+
+        let mut store = self.clone();
+        store.ranking_field = Some(embedded_field);
+
+        Ok(store)
+    }
+
+    /// Returns true if an index on the table includes `column`.
+    async fn column_has_index(&self, column: &str) -> Result<bool> {
+        let pool = self.pool_get_or_initialize().await?;
+
+        Ok(sqlx::query_scalar(
+            "SELECT EXISTS ( \
+                SELECT 1 FROM pg_index i \
+                JOIN pg_attribute a ON a.attrelid = i.indrelid AND a.attnum = ANY(i.indkey) \
+                WHERE i.indrelid = to_regclass($1) AND a.attname = $2 \
+             )",
+        )
+        .bind(&self.table_name)
+        .bind(column)
+        .fetch_one(pool)
+        .await?)
+    }
+
+    /// Builds an index of the configured type on a vector column without locking out writes.
+    async fn build_column_index(&self, column: &str) -> Result<()> {
+        let index_type = self.resolve_index_type(self.index_type).await?;
+        let sql = self.generate_named_index_sql(
+            index_type,
+            &self.column_index_name(column, index_type),
+            column,
+            true,
+        )?;
+
+        tracing::info!(
+            column,
+            "Building vector index before switching ranking field"
+        );
+
+        let pool = self.pool_get_or_initialize().await?;
+        sqlx::query(&sql)
+            .execute(pool)
+            .await
+            .map_err(|err| anyhow!(err).context(format!("Failed to index `{column}`")))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pgvector::fixtures::TestContext;
+    use futures_util::TryStreamExt;
+    use std::collections::HashSet;
+    use swiftide_core::{
+        indexing::{self, EmbeddedField},
+        querying::{search_strategies::SimilaritySingleEmbedding, states, Query},
+        MockEmbeddingModel, Persist, Retrieve,
+    };
+
+    #[test_log::test(tokio::test)]
+    async fn test_add_backfill_and_switch_vector_field() {
+        let test_context =
+            TestContext::setup_with_cfg(None, HashSet::from([EmbeddedField::Combined]))
+                .await
+                .expect("Test setup failed");
+
+        let nodes = ["north", "east", "south"]
+            .into_iter()
+            .map(|chunk| {
+                indexing::Node::new(chunk)
+                    .with_vectors([(EmbeddedField::Combined, vec![1.0; 384])])
+                    .to_owned()
+            })
+            .collect();
+
+        test_context
+            .pgv_storage
+            .batch_store(nodes)
+            .await
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        let upgraded = test_context
+            .pgv_storage
+            .add_vector_field(EmbeddedField::Chunk, 3)
+            .await
+            .unwrap();
+
+        let mut embed_model = MockEmbeddingModel::new();
+        embed_model.expect_embed().returning(|chunks| {
+            Ok(chunks
+                .iter()
+                .map(|chunk| match chunk.as_str() {
+                    "north" => vec![1.0, 0.0, 0.0],
+                    "east" => vec![0.0, 1.0, 0.0],
+                    _ => vec![0.0, 0.0, 1.0],
+                })
+                .collect())
+        });
+
+        let mut search_strategy = SimilaritySingleEmbedding::<String>::default();
+        search_strategy.with_top_k(1);
+
+        // Before the switch, the upgraded store still ranks on the old column
+        let mut query = Query::<states::Pending>::new("test_query");
+        query.embedding = Some(vec![1.0; 384]);
+
+        let result = upgraded.retrieve(&search_strategy, query).await.unwrap();
+        assert_eq!(result.documents().len(), 1);
+
+        let backfilled = upgraded
+            .backfill_vector_field(&EmbeddedField::Chunk, &embed_model)
+            .await
+            .unwrap();
+        assert_eq!(backfilled, 3);
+
+        let upgraded = upgraded
+            .switch_ranking_field(EmbeddedField::Chunk)
+            .await
+            .unwrap();
+
+        // Switching indexes the new column
+        let pool = upgraded.get_pool().await.unwrap();
+        let indexed: bool = sqlx::query_scalar(
+            "SELECT EXISTS (SELECT 1 FROM pg_indexes \
+             WHERE tablename = 'swiftide_pgvector_test' AND indexdef LIKE '%(vector_chunk %')",
+        )
+        .fetch_one(pool)
+        .await
+        .unwrap();
+        assert!(indexed);
+
+        let mut query = Query::<states::Pending>::new("test_query");
+        query.embedding = Some(vec![0.0, 1.0, 0.0]);
+
+        let result = upgraded.retrieve(&search_strategy, query).await.unwrap();
+        assert_eq!(result.documents(), ["east"]);
+
+        // The original store still ranks on the old column
+        let mut query = Query::<states::Pending>::new("test_query");
+        query.embedding = Some(vec![1.0; 384]);
+
+        let result = test_context
+            .pgv_storage
+            .retrieve(&search_strategy, query)
+            .await
+            .unwrap();
+        assert_eq!(result.documents().len(), 1);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_backfill_rejects_embeddings_of_wrong_size() {
+        let test_context =
+            TestContext::setup_with_cfg(None, HashSet::from([EmbeddedField::Combined]))
+                .await
+                .expect("Test setup failed");
+
+        let node = indexing::Node::new("north")
+            .with_vectors([(EmbeddedField::Combined, vec![1.0; 384])])
+            .to_owned();
+        test_context.pgv_storage.store_nodes(&[node]).await.unwrap();
+
+        let upgraded = test_context
+            .pgv_storage
+            .add_vector_field(EmbeddedField::Chunk, 3)
+            .await
+            .unwrap();
+
+        let mut embed_model = MockEmbeddingModel::new();
+        embed_model
+            .expect_embed()
+            .returning(|chunks| Ok(chunks.iter().map(|_| vec![1.0, 0.0]).collect()));
+
+        let err = upgraded
+            .backfill_vector_field(&EmbeddedField::Chunk, &embed_model)
+            .await
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("Vector field `vector_chunk` expects 3 dimensions, got 2"),
+            "Unexpected error: {err}"
+        );
+    }
+}
@@ -0,0 +1,379 @@
+//! Introspection of the live table schema.
+//!
+//! Reads the columns of the table from the database catalog:
+//! - [`PgVector::describe`] lists the live columns with their types
+//! - [`PgVector::validate_schema_consistency`] compares them against the configured fields
+//! - [`PgVector::validate_vector_dimensions`] checks the declared vector dimensions on setup
+//! - [`PgVector::list_managed_tables`] finds all tables created by [`PgVector`] in a database
+//!
+//! Validation catches drift between the configuration and a table created by an earlier version
+//! of the configuration or altered by hand.
+use crate::pgvector::{pgv_table_types::FieldConfig, PgVector};
+use anyhow::{anyhow, Result};
+use sqlx::{PgConnection, PgPool};
+
+/// Comment set on tables during setup, marking them as managed by [`PgVector`].
+pub(crate) const MANAGED_TABLE_COMMENT: &str = "Managed by swiftide pgvector";
+
+/// A column of the live table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnDescription {
+    /// The column name
+    pub name: String,
+    /// The column type as formatted by `PostgreSQL`, e.g. `jsonb` or `vector(384)`
+    pub data_type: String,
+    /// Whether the column accepts `NULL`
+    pub nullable: bool,
+}
+
+/// The live schema of the table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TableDescription {
+    /// The table name
+    pub table_name: String,
+    /// The columns of the table, in table order
+    pub columns: Vec<ColumnDescription>,
+}
+
+impl TableDescription {
+    /// Returns the column with the given name, if it exists.
+    pub fn column(&self, name: &str) -> Option<&ColumnDescription> {
+        self.columns.iter().find(|column| column.name == name)
+    }
+}
+
+impl PgVector {
+    /// Describes the live table as stored in the database catalog.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the table does not exist or the catalog query fails.
+    pub async fn describe(&self) -> Result<TableDescription> {
+        if !self.table_exists().await? {
+            return Err(anyhow!("Table `{}` does not exist", self.table_name));
+        }
+
+        let pool = self.pool_get_or_initialize().await?;
+
+        let rows: Vec<(String, String, bool)> = sqlx::query_as(
+            "SELECT attname::text, format_type(atttypid, atttypmod), NOT attnotnull \
+             FROM pg_attribute \
+             WHERE attrelid = to_regclass($1) AND attnum > 0 AND NOT attisdropped \
+             ORDER BY attnum",
+        )
+        .bind(&self.table_name)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(TableDescription {
+            table_name: self.table_name.clone(),
+            columns: rows
+                .into_iter()
+                .map(|(name, data_type, nullable)| ColumnDescription {
+                    name,
+                    data_type,
+                    nullable,
+                })
+                .collect(),
+        })
+    }
+
+    /// Lists all tables in the database that were set up by [`PgVector`].
+    ///
+    /// Tables are marked with a comment during setup, unless they already have a comment, which
+    /// is left untouched. Tables whose comment was replaced are therefore not listed. Tables
+    /// outside the search path are returned qualified with their schema.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the catalog query fails.
+    pub async fn list_managed_tables(pool: &PgPool) -> Result<Vec<String>> {
+        let tables = sqlx::query_scalar(
+            "SELECT c.oid::regclass::text \
+             FROM pg_class c \
+             WHERE c.relkind IN ('r', 'p') AND obj_description(c.oid, 'pg_class') = $1 \
+             ORDER BY 1",
+        )
+        .bind(MANAGED_TABLE_COMMENT)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(tables)
+    }
+
+    /// Validates that the live table matches the configured fields.
+    ///
+    /// Every configured field must exist as a column of the expected type, and the table must
+    /// not have columns that are not configured. This includes the columns selected by
+    /// retrieval.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error describing the first discrepancy found, or if the table cannot be
+    /// described.
+    pub async fn validate_schema_consistency(&self) -> Result<()> {
+        let description = self.describe().await?;
+
+        for field in &self.fields {
+            let name = field.field_name();
+            let column = description.column(name).ok_or_else(|| {
+                anyhow!(
+                    "Column `{name}` is configured but missing from table `{}`",
+                    self.table_name
+                )
+            })?;
+
+            if !self.column_type_matches(field, &column.data_type) {
+                return Err(anyhow!(
+                    "Column `{name}` of table `{}` has type `{}`, expected `{}`",
+                    self.table_name,
+                    column.data_type,
+                    self.expected_column_type(field)
+                ));
+            }
+        }
+
+        if let Some(column) = description
+            .columns
+            .iter()
+            .find(|column| !self.fields.iter().any(|f| f.field_name() == column.name))
+        {
+            return Err(anyhow!(
+                "Column `{}` of table `{}` is not configured",
+                column.name,
+                self.table_name
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Validates that existing vector columns are declared with the configured vector sizes.
+    ///
+    /// Runs on `conn` right after creating the table during setup, so a changed `vector_size`
+    /// fails before any other DDL with a clear error instead of failing stores and retrieves
+    /// later. Passes if the table does not exist yet, and skips fields whose size is not resolved
+    /// or columns declared without a dimension.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a vector column's declared dimension differs from the configured
+    /// size, or if the table cannot be described.
+    pub(crate) async fn validate_vector_dimensions(&self, conn: &mut PgConnection) -> Result<()> {
+        let columns: Vec<(String, String)> = sqlx::query_as(
+            "SELECT attname::text, format_type(atttypid, atttypmod) \
+             FROM pg_attribute \
+             WHERE attrelid = to_regclass($1) AND attnum > 0 AND NOT attisdropped",
+        )
+        .bind(&self.table_name)
+        .fetch_all(conn)
+        .await?;
+
+        for field in &self.fields {
+            let FieldConfig::Vector(config) = field else {
+                continue;
+            };
+            let Ok(expected) = self.vector_size_for(config) else {
+                continue;
+            };
+
+            let declared = columns
+                .iter()
+                .find(|(name, _)| *name == config.field)
+                .and_then(|(_, data_type)| data_type.strip_prefix("vector("))
+                .and_then(|dimension| dimension.strip_suffix(')'))
+                .and_then(|dimension| dimension.parse::<i32>().ok());
+
+            if let Some(declared) = declared.filter(|declared| *declared != expected) {
+                return Err(anyhow!(
+                    "Vector column `{}` of table `{}` has {declared} dimensions, but the \
+                     configured vector size is {expected}; migrate the column or use a new table \
+                     to change the vector size",
+                    config.field,
+                    self.table_name
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn table_exists(&self) -> Result<bool> {
+        let pool = self.pool_get_or_initialize().await?;
+
+        Ok(sqlx::query_scalar("SELECT to_regclass($1) IS NOT NULL")
+            .bind(&self.table_name)
+            .fetch_one(pool)
+            .await?)
+    }
+
+    /// Returns the type of a configured field as formatted by `PostgreSQL`.
+    ///
+    /// Vector columns without a resolved size are reported as `vector`.
+    fn expected_column_type(&self, field: &FieldConfig) -> String {
+        match field {
+            FieldConfig::ID | FieldConfig::RunId => "uuid".to_string(),
+            FieldConfig::Chunk | FieldConfig::Language(_) | FieldConfig::Model => {
+                "text".to_string()
+            }
+            FieldConfig::Metadata(_) => "jsonb".to_string(),
+            FieldConfig::Vector(config) => match self.vector_size_for(config) {
+                Ok(size) => format!("vector({size})"),
+                Err(_) => "vector".to_string(),
+            },
+            FieldConfig::FullText(_) => "tsvector".to_string(),
+            FieldConfig::CreatedAt | FieldConfig::UpdatedAt => {
+                "timestamp with time zone".to_string()
+            }
+        }
+    }
+
+    fn column_type_matches(&self, field: &FieldConfig, data_type: &str) -> bool {
+        let expected = self.expected_column_type(field);
+
+        // Any dimension matches while the vector size is not known
+        if expected == "vector" {
+            return data_type.starts_with("vector");
+        }
+
+        expected == data_type
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pgvector::{fixtures::TestContext, PgVector};
+    use std::collections::HashSet;
+    use swiftide_core::{indexing::EmbeddedField, Persist};
+
+    #[test_log::test(tokio::test)]
+    async fn test_validate_schema_consistency_detects_drift() {
+        let test_context = TestContext::setup_with_cfg(
+            vec!["source"].into(),
+            HashSet::from([EmbeddedField::Combined]),
+        )
+        .await
+        .expect("Test setup failed");
+
+        let description = test_context.pgv_storage.describe().await.unwrap();
+        assert_eq!(
+            description.column("vector_combined").unwrap().data_type,
+            "vector(384)"
+        );
+
+        test_context
+            .pgv_storage
+            .validate_schema_consistency()
+            .await
+            .unwrap();
+
+        let pool = test_context.pgv_storage.get_pool().await.unwrap();
+        sqlx::query("ALTER TABLE swiftide_pgvector_test ALTER COLUMN meta_source TYPE text")
+            .execute(pool)
+            .await
+            .unwrap();
+
+        let err = test_context
+            .pgv_storage
+            .validate_schema_consistency()
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Column `meta_source` of table `swiftide_pgvector_test` has type `text`, expected `jsonb`"
+        );
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_list_managed_tables() {
+        let test_context =
+            TestContext::setup_with_cfg(None, HashSet::from([EmbeddedField::Combined]))
+                .await
+                .expect("Test setup failed");
+
+        let other = PgVector::builder()
+            .db_url(test_context.pgv_storage.db_url.clone())
+            .vector_size(384)
+            .with_vector(EmbeddedField::Combined)
+            .table_name("swiftide_pgvector_other")
+            .build()
+            .unwrap();
+        other.setup().await.unwrap();
+
+        let pool = test_context.pgv_storage.get_pool().await.unwrap();
+        sqlx::query("CREATE TABLE unmanaged (id INT)")
+            .execute(pool)
+            .await
+            .unwrap();
+
+        let tables = PgVector::list_managed_tables(pool).await.unwrap();
+        assert_eq!(
+            tables,
+            ["swiftide_pgvector_other", "swiftide_pgvector_test"]
+        );
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_setup_keeps_existing_table_comment() {
+        let test_context =
+            TestContext::setup_with_cfg(None, HashSet::from([EmbeddedField::Combined]))
+                .await
+                .expect("Test setup failed");
+
+        let pool = test_context.pgv_storage.get_pool().await.unwrap();
+        sqlx::query("COMMENT ON TABLE swiftide_pgvector_test IS 'Owned by the search team'")
+            .execute(pool)
+            .await
+            .unwrap();
+
+        test_context.pgv_storage.setup().await.unwrap();
+
+        let comment: Option<String> = sqlx::query_scalar(
+            "SELECT obj_description('swiftide_pgvector_test'::regclass, 'pg_class')",
+        )
+        .fetch_one(pool)
+        .await
+        .unwrap();
+        assert_eq!(comment.as_deref(), Some("Owned by the search team"));
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_setup_rejects_changed_vector_size() {
+        let test_context =
+            TestContext::setup_with_cfg(None, HashSet::from([EmbeddedField::Combined]))
+                .await
+                .expect("Test setup failed");
+
+        let resized = PgVector::builder()
+            .db_url(test_context.pgv_storage.db_url.clone())
+            .vector_size(8)
+            .with_vector(EmbeddedField::Combined)
+            .table_name("swiftide_pgvector_test")
+            .build()
+            .unwrap();
+
+        // Without a comment, a setup that got past the validation would mark the table
+        let pool = test_context.pgv_storage.get_pool().await.unwrap();
+        sqlx::query("COMMENT ON TABLE swiftide_pgvector_test IS NULL")
+            .execute(pool)
+            .await
+            .unwrap();
+
+        let err = resized.setup().await.unwrap_err();
+
+        let comment: Option<String> = sqlx::query_scalar(
+            "SELECT obj_description('swiftide_pgvector_test'::regclass, 'pg_class')",
+        )
+        .fetch_one(pool)
+        .await
+        .unwrap();
+        assert_eq!(comment, None, "Setup should fail before any DDL");
+
+        assert_eq!(
+            err.to_string(),
+            "Vector column `vector_combined` of table `swiftide_pgvector_test` has 384 \
+             dimensions, but the configured vector size is 8; migrate the column or use a new \
+             table to change the vector size"
+        );
+    }
+}
@@ -0,0 +1,255 @@
+//! Storage usage utilities for capacity planning.
+//!
+//! Estimates how many bytes each column of the table consumes:
+//! - Column sizes are measured with `pg_column_size` on a sample of rows
+//! - Sampled sizes are extrapolated to the full row count
+//! - Columns are summarized into chunk, metadata and vector totals
+//!
+//! [`PgVector::compact`] reclaims the space left behind by deleted rows.
+use crate::pgvector::{pgv_table_types::FieldConfig, PgVector};
+use anyhow::{anyhow, Result};
+use sqlx::Row;
+
+/// Maximum number of rows measured by [`PgVector::storage_breakdown`].
+const STORAGE_SAMPLE_ROWS: i64 = 10_000;
+
+/// Estimated storage used by a single column.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnStorage {
+    /// The column name
+    pub column: String,
+    /// Estimated bytes used by the column across all rows
+    pub bytes: u64,
+}
+
+/// Estimated storage used by the columns of the table.
+///
+/// Sizes are the stored (possibly compressed) datum sizes reported by `pg_column_size`. They
+/// exclude row headers, indexes and free space.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StorageBreakdown {
+    /// Number of rows in the table
+    pub total_rows: u64,
+    /// Number of rows measured
+    pub sampled_rows: u64,
+    /// Estimated bytes per column, in schema order
+    pub columns: Vec<ColumnStorage>,
+    /// Estimated bytes used by the chunk text
+    pub chunk_bytes: u64,
+    /// Estimated bytes used by all metadata columns
+    pub metadata_bytes: u64,
+    /// Estimated bytes used by all vector columns
+    pub vector_bytes: u64,
+}
+
+impl StorageBreakdown {
+    /// Estimated bytes used by all columns.
+    pub fn total_bytes(&self) -> u64 {
+        self.columns.iter().map(|column| column.bytes).sum()
+    }
+}
+
+impl PgVector {
+    /// Estimates the storage used by each column of the table.
+    ///
+    /// Column sizes are summed over up to 10,000 rows, taken in physical order, and scaled to the
+    /// total row count. The estimate is exact for tables up to that size.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the table or a column name is invalid or a query fails.
+    #[allow(
+        clippy::cast_sign_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_precision_loss
+    )]
+    pub async fn storage_breakdown(&self) -> Result<StorageBreakdown> {
+        if !Self::is_valid_identifier(&self.table_name) {
+            return Err(anyhow!("Invalid table name"));
+        }
+
+        let columns: Vec<&str> = self.fields.iter().map(FieldConfig::field_name).collect();
+        if let Some(invalid) = columns.iter().find(|c| !Self::is_valid_identifier(c)) {
+            return Err(anyhow!("Invalid column name: {invalid}"));
+        }
+
+        let pool = self.pool_get_or_initialize().await?;
+
+        let total_rows: i64 =
+            sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {}", self.table_name))
+                .fetch_one(pool)
+                .await?;
+
+        let sums = columns
+            .iter()
+            .map(|column| format!("COALESCE(SUM(pg_column_size({column})), 0)::BIGINT"))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        let sql = format!(
+            "SELECT COUNT(*), {sums} FROM (SELECT {} FROM {} LIMIT $1) AS sample",
+            columns.join(", "),
+            self.table_name
+        );
+
+        let row = sqlx::query(&sql)
+            .bind(STORAGE_SAMPLE_ROWS)
+            .fetch_one(pool)
+            .await?;
+
+        let sampled_rows: i64 = row.try_get(0)?;
+        let scale = if sampled_rows > 0 {
+            total_rows as f64 / sampled_rows as f64
+        } else {
+            0.0
+        };
+
+        let mut breakdown = StorageBreakdown {
+            total_rows: total_rows as u64,
+            sampled_rows: sampled_rows as u64,
+            columns: Vec::with_capacity(columns.len()),
+            chunk_bytes: 0,
+            metadata_bytes: 0,
+            vector_bytes: 0,
+        };
+
+        for (idx, field) in self.fields.iter().enumerate() {
+            let sampled_bytes: i64 = row.try_get(idx + 1)?;
+            let bytes = (sampled_bytes as f64 * scale).round() as u64;
+
+            match field {
+                FieldConfig::Chunk => breakdown.chunk_bytes += bytes,
+                FieldConfig::Metadata(_) => breakdown.metadata_bytes += bytes,
+                FieldConfig::Vector(_) => breakdown.vector_bytes += bytes,
+                _ => {}
+            }
+
+            breakdown.columns.push(ColumnStorage {
+                column: field.field_name().to_string(),
+                bytes,
+            });
+        }
+
+        Ok(breakdown)
+    }
+
+    /// Compacts the table with `VACUUM (FULL)`, returning the space of deleted and updated rows
+    /// to the operating system.
+    ///
+    /// A full vacuum rewrites the table and all its indexes, including the vector index, into new
+    /// files. It holds an `ACCESS EXCLUSIVE` lock for the whole duration, blocking all reads and
+    /// writes, and temporarily needs disk space for a full copy of the table. Only run it during
+    /// maintenance windows, e.g. after deleting a large share of the rows. A plain `VACUUM`,
+    /// which autovacuum runs regularly, does not block but only makes the space reusable within
+    /// the table.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the table name is invalid or the vacuum fails.
+    pub async fn compact(&self) -> Result<()> {
+        if !Self::is_valid_identifier(&self.table_name) {
+            return Err(anyhow!("Invalid table name"));
+        }
+
+        let pool = self.pool_get_or_initialize().await?;
+
+        // VACUUM cannot run in a transaction, so it is sent as a simple query
+        sqlx::raw_sql(&format!("VACUUM (FULL) {}", self.table_name))
+            .execute(pool)
+            .await
+            .map_err(|err| anyhow!(err).context("Failed to compact table"))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pgvector::fixtures::TestContext;
+    use std::collections::HashSet;
+    use swiftide_core::indexing::{self, EmbeddedField};
+
+    #[test_log::test(tokio::test)]
+    async fn test_storage_breakdown() {
+        let test_context = TestContext::setup_with_cfg(
+            vec!["source"].into(),
+            HashSet::from([EmbeddedField::Combined]),
+        )
+        .await
+        .expect("Test setup failed");
+
+        let nodes: Vec<_> = (0..10)
+            .map(|i| {
+                indexing::Node::new(format!("chunk number {i}"))
+                    .with_metadata(("source", "docs"))
+                    .with_vectors([(EmbeddedField::Combined, vec![0.5; 384])])
+                    .to_owned()
+            })
+            .collect();
+
+        test_context.pgv_storage.store_nodes(&nodes).await.unwrap();
+
+        let breakdown = test_context.pgv_storage.storage_breakdown().await.unwrap();
+
+        assert_eq!(breakdown.total_rows, 10);
+        assert_eq!(breakdown.sampled_rows, 10);
+
+        // Each 384 dimension vector takes 4 bytes per dimension plus a small header
+        assert!(breakdown.vector_bytes >= 10 * 384 * 4);
+        assert!(breakdown.vector_bytes < 10 * (384 * 4 + 64));
+
+        assert!(breakdown.chunk_bytes > 0);
+        assert!(breakdown.metadata_bytes > 0);
+        assert!(breakdown.chunk_bytes < breakdown.vector_bytes);
+
+        let categorized = breakdown.chunk_bytes + breakdown.metadata_bytes + breakdown.vector_bytes;
+        assert!(breakdown.total_bytes() >= categorized);
+
+        let columns: Vec<_> = breakdown
+            .columns
+            .iter()
+            .map(|c| c.column.as_str())
+            .collect();
+        assert_eq!(columns, ["id", "chunk", "vector_combined", "meta_source"]);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_compact_reclaims_deleted_rows() {
+        let test_context =
+            TestContext::setup_with_cfg(None, HashSet::from([EmbeddedField::Combined]))
+                .await
+                .expect("Test setup failed");
+
+        let nodes: Vec<_> = (0..500)
+            .map(|i| {
+                indexing::Node::new(format!("chunk number {i}"))
+                    .with_vectors([(EmbeddedField::Combined, vec![0.5; 384])])
+                    .to_owned()
+            })
+            .collect();
+
+        test_context.pgv_storage.store_nodes(&nodes).await.unwrap();
+
+        let pool = test_context.pgv_storage.get_pool().await.unwrap();
+        let table_size = || async {
+            sqlx::query_scalar::<_, i64>("SELECT pg_relation_size('swiftide_pgvector_test')")
+                .fetch_one(pool)
+                .await
+                .unwrap()
+        };
+
+        sqlx::query("DELETE FROM swiftide_pgvector_test WHERE chunk <> 'chunk number 0'")
+            .execute(pool)
+            .await
+            .unwrap();
+
+        let size_before = table_size().await;
+        test_context.pgv_storage.compact().await.unwrap();
+        let size_after = table_size().await;
+
+        assert!(
+            size_after < size_before,
+            "Table did not shrink: {size_before} -> {size_after}"
+        );
+    }
+}
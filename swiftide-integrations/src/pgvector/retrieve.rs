@@ -1,12 +1,16 @@
-use crate::pgvector::{PgVector, PgVectorBuilder};
+use crate::pgvector::{pgv_table_types::FieldConfig, PgVector, PgVectorBuilder};
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
 use pgvector::Vector;
-use sqlx::{prelude::FromRow, types::Uuid};
+use sqlx::{
+    postgres::PgArguments, prelude::FromRow, query::QueryAs, types::Uuid, PgPool, Postgres, Row,
+};
+use std::{collections::HashMap, future::Future};
 use swiftide_core::{
     querying::{search_strategies::SimilaritySingleEmbedding, states, Query},
     Retrieve,
 };
+use tokio::time::sleep;
 
 #[allow(dead_code)]
 #[derive(Debug, Clone, FromRow)]
@@ -15,129 +19,2224 @@ struct VectorSearchResult {
     chunk: String,
 }
 
-#[allow(clippy::redundant_closure_for_method_calls)]
-#[async_trait]
-impl Retrieve<SimilaritySingleEmbedding<String>> for PgVector {
-    #[tracing::instrument]
-    async fn retrieve(
+/// The inputs shared by similarity retrieves, see [`PgVector::search_context`].
+struct SearchContext<'a> {
+    pool: &'a PgPool,
+    /// The query embedding, bound as `$1`
+    embedding: Vector,
+    /// The vector column results are ranked on
+    vector_column: String,
+    /// Conditions combined into the `WHERE` clause
+    conditions: Vec<String>,
+    /// The number of results, bound as `$2`
+    top_k: i64,
+}
+
+impl SearchContext<'_> {
+    /// Excludes the IDs of `options`, which are bound as parameter `$param`.
+    fn exclude_ids(&mut self, options: &RetrieveOptions, param: usize) {
+        if !options.exclude_ids.is_empty() {
+            self.conditions.push(format!("id <> ALL(${param})"));
+        }
+    }
+
+    /// Returns the `WHERE` clause of all conditions.
+    fn where_clause(&self) -> String {
+        where_clause(&self.conditions)
+    }
+}
+
+/// Returns a `WHERE` clause combining `conditions`, or an empty string if there are none.
+fn where_clause(conditions: &[String]) -> String {
+    if conditions.is_empty() {
+        String::new()
+    } else {
+        format!(" WHERE {}", conditions.join(" AND "))
+    }
+}
+
+/// A retrieved document together with its own nearest neighbors.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocumentWithNeighbors {
+    /// The retrieved document
+    pub document: String,
+    /// The nearest neighbors of the document, closest first, excluding the document itself
+    pub neighbors: Vec<String>,
+}
+
+/// Retrieved documents together with the pairwise cosine distances between them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocumentsWithDistances {
+    /// The retrieved documents, closest to the query first
+    pub documents: Vec<String>,
+    /// Symmetric matrix of cosine distances, where `distances[i][j]` is the distance between
+    /// `documents[i]` and `documents[j]`
+    pub distances: Vec<Vec<f64>>,
+}
+
+/// A retrieved child chunk together with its distance to the query.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChildChunk {
+    /// The chunk text
+    pub chunk: String,
+    /// Cosine distance between the chunk and the query
+    pub distance: f64,
+}
+
+/// Retrieved child chunks nested under the parent document they belong to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParentGroup {
+    /// The parent document ID, read from the parent metadata field
+    pub parent_id: String,
+    /// The matching children of the parent, closest first
+    pub children: Vec<ChildChunk>,
+}
+
+/// A retrieved chunk located in its source document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Citation {
+    /// The source document of the chunk
+    pub source: String,
+    /// Character offset of the start of the chunk in the source
+    pub start: usize,
+    /// Character offset of the end of the chunk in the source
+    pub end: usize,
+    /// The chunk text
+    pub text: String,
+}
+
+/// Metadata fields holding the source and character offsets of each chunk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CitationConfig {
+    source_field: String,
+    start_field: String,
+    end_field: String,
+}
+
+impl CitationConfig {
+    /// Creates a citation configuration reading the source and the start and end offsets from
+    /// the given metadata fields.
+    pub fn new(
+        source_field: impl Into<String>,
+        start_field: impl Into<String>,
+        end_field: impl Into<String>,
+    ) -> Self {
+        Self {
+            source_field: source_field.into(),
+            start_field: start_field.into(),
+            end_field: end_field.into(),
+        }
+    }
+}
+
+/// Per-query options for [`PgVector::retrieve_with_options`] and
+/// [`PgVector::retrieve_projected`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RetrieveOptions {
+    exclude_ids: Vec<Uuid>,
+    projection: Option<Projection>,
+    tie_break: Option<(String, SortOrder)>,
+}
+
+impl RetrieveOptions {
+    /// Orders results with equal distances by the metadata field `key`.
+    ///
+    /// Values are compared as JSON, so numbers compare numerically and strings, such as
+    /// RFC 3339 timestamps, lexicographically. Rows without the field come last.
+    #[must_use]
+    pub fn with_tie_break(mut self, key: impl Into<String>, order: SortOrder) -> Self {
+        self.tie_break = Some((key.into(), order));
+        self
+    }
+
+    /// Selects the columns returned by [`PgVector::retrieve_projected`].
+    ///
+    /// Without a projection, the ID, chunk and score are returned.
+    #[must_use]
+    pub fn with_projection(mut self, projection: Projection) -> Self {
+        self.projection = Some(projection);
+        self
+    }
+
+    /// Excludes rows with the given IDs from the results, e.g. results that were already shown.
+    #[must_use]
+    pub fn with_exclude_ids(mut self, exclude_ids: impl IntoIterator<Item = Uuid>) -> Self {
+        self.exclude_ids.extend(exclude_ids);
+        self
+    }
+
+    /// Binds the excluded IDs, if any, as the next parameter of the query.
+    fn bind_exclude_ids<'q, O>(
+        &'q self,
+        query: QueryAs<'q, Postgres, O, PgArguments>,
+    ) -> QueryAs<'q, Postgres, O, PgArguments> {
+        if self.exclude_ids.is_empty() {
+            query
+        } else {
+            query.bind(&self.exclude_ids)
+        }
+    }
+}
+
+/// Sort direction of a tie-break, see [`RetrieveOptions::with_tie_break`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortOrder {
+    /// Smallest values first
+    #[default]
+    Ascending,
+    /// Largest values first
+    Descending,
+}
+
+/// The columns returned per row by [`PgVector::retrieve_projected`].
+///
+/// Any subset of the ID, chunk, score and specific metadata fields can be selected. Columns that
+/// are not selected are not read from the database.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Projection {
+    id: bool,
+    chunk: bool,
+    score: bool,
+    metadata: Vec<String>,
+}
+
+impl Projection {
+    /// Creates an empty projection, to which columns are added.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Includes the row ID.
+    #[must_use]
+    pub fn id(mut self) -> Self {
+        self.id = true;
+        self
+    }
+
+    /// Includes the chunk text.
+    #[must_use]
+    pub fn chunk(mut self) -> Self {
+        self.chunk = true;
+        self
+    }
+
+    /// Includes the cosine similarity between the row and the query.
+    #[must_use]
+    pub fn score(mut self) -> Self {
+        self.score = true;
+        self
+    }
+
+    /// Includes the value of the metadata field `key`.
+    #[must_use]
+    pub fn metadata(mut self, key: impl Into<String>) -> Self {
+        self.metadata.push(key.into());
+        self
+    }
+
+    fn all() -> Self {
+        Self::new().id().chunk().score()
+    }
+}
+
+/// A row retrieved with a [`Projection`]; columns that were not selected are `None` or absent.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProjectedRow {
+    /// The row ID
+    pub id: Option<Uuid>,
+    /// The chunk text
+    pub chunk: Option<String>,
+    /// Cosine similarity between the row and the query, `1 - cosine distance`
+    pub score: Option<f64>,
+    /// The selected metadata values by key; missing values are absent
+    pub metadata: HashMap<String, serde_json::Value>,
+}
+
+/// A structured metadata query combined with vector similarity, like an Elasticsearch bool query.
+///
+/// - `must` clauses are hard filters: every one has to match
+/// - `must_not` clauses are exclusions: none may match; rows without the field are kept
+/// - `should` clauses are boosts: each match subtracts its boost from the cosine distance
+///
+/// All clauses compare a metadata field to a string value.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BoolQuery {
+    must: Vec<(String, String)>,
+    should: Vec<(String, String, f64)>,
+    must_not: Vec<(String, String)>,
+}
+
+impl BoolQuery {
+    /// Creates an empty bool query, which ranks by similarity alone.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requires the metadata field `key` to equal `value`.
+    #[must_use]
+    pub fn must(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.must.push((key.into(), value.into()));
+        self
+    }
+
+    /// Ranks results where the metadata field `key` equals `value` higher by `boost`.
+    #[must_use]
+    pub fn should(mut self, key: impl Into<String>, value: impl Into<String>, boost: f64) -> Self {
+        self.should.push((key.into(), value.into(), boost));
+        self
+    }
+
+    /// Excludes results where the metadata field `key` equals `value`.
+    #[must_use]
+    pub fn must_not(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.must_not.push((key.into(), value.into()));
+        self
+    }
+}
+
+/// How similarity retrieval handles a query that has no embedding.
+///
+/// Applies to every retrieve taking a pending query. Only [`Retrieve::retrieve`] and
+/// [`PgVector::retrieve_with_options`] can retrieve by filter alone; the other retrieves rank,
+/// score, or group by distance and return no results for both `FilterOnly` and `Empty`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NoEmbeddingBehavior {
+    /// Fail the retrieval
+    #[default]
+    Error,
+    /// Retrieve up to `top_k` documents matching only the filter, in no particular order
+    FilterOnly,
+    /// Return no documents
+    Empty,
+}
+
+/// SQLSTATE of a serialization failure under `REPEATABLE READ` or `SERIALIZABLE` isolation.
+const SERIALIZATION_FAILURE: &str = "40001";
+
+/// Default number of candidates fetched per requested result when re-ranking for diversity.
+const DIVERSITY_CANDIDATE_MULTIPLIER: u64 = 3;
+
+/// Soft penalty demoting results that share a metadata value with higher ranked results.
+///
+/// Each time a value has already been selected, later results with that value have `penalty`
+/// added to their distance, so results from a dominant source are interleaved with others
+/// instead of filling the top of the list.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiversityPenalty {
+    metadata_field: String,
+    penalty: f64,
+    candidate_multiplier: u64,
+}
+
+impl DiversityPenalty {
+    /// Creates a penalty of `penalty` distance per repeated value of `metadata_field`.
+    pub fn new(metadata_field: impl Into<String>, penalty: f64) -> Self {
+        Self {
+            metadata_field: metadata_field.into(),
+            penalty,
+            candidate_multiplier: DIVERSITY_CANDIDATE_MULTIPLIER,
+        }
+    }
+
+    /// Sets how many candidates are fetched per requested result before re-ranking.
+    #[must_use]
+    pub fn with_candidate_multiplier(mut self, candidate_multiplier: u64) -> Self {
+        self.candidate_multiplier = candidate_multiplier.max(1);
+        self
+    }
+
+    /// Greedily selects up to `top_k` candidates, penalizing repeated metadata values.
+    ///
+    /// Candidates are `(document, metadata value, distance)`.
+    #[allow(clippy::cast_precision_loss)]
+    fn rerank(
+        &self,
+        mut candidates: Vec<(String, Option<String>, f64)>,
+        top_k: usize,
+    ) -> Vec<String> {
+        let mut seen: HashMap<Option<String>, usize> = HashMap::new();
+        let mut selected = Vec::with_capacity(top_k.min(candidates.len()));
+
+        while selected.len() < top_k && !candidates.is_empty() {
+            let penalized = |(_, value, distance): &(String, Option<String>, f64)| {
+                distance + self.penalty * seen.get(value).copied().unwrap_or_default() as f64
+            };
+
+            // Ties keep the original (distance) order
+            let best = candidates
+                .iter()
+                .enumerate()
+                .min_by(|(_, a), (_, b)| penalized(a).total_cmp(&penalized(b)))
+                .map(|(idx, _)| idx)
+                .expect("Candidates are not empty");
+
+            let (document, value, _) = candidates.remove(best);
+            *seen.entry(value).or_default() += 1;
+            selected.push(document);
+        }
+
+        selected
+    }
+}
+
+impl PgVector {
+    /// Translates a `key = "value"` filter into a SQL condition on the metadata column.
+    ///
+    /// The value is either unquoted, taken as is after trimming whitespace, or wrapped in a
+    /// matched pair of double quotes, in which `\"` and `\\` escape a quote and a backslash.
+    /// See [`PgVector::parse_filter`].
+    ///
+    /// The `model` key filters on the embedding model column when it is configured and no
+    /// metadata field of the same name is.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the filter is not of the form `key = value`.
+    pub(crate) fn filter_condition(&self, filter: &str) -> Result<String> {
+        let (key, value) = Self::parse_filter(filter)?;
+        tracing::debug!(
+            "Filter being applied: key = {:#?}, value = {:#?}",
+            key,
+            value
+        );
+
+        let escaped_key = key.replace('\'', "''");
+        let escaped_value = value.replace('\'', "''");
+
+        if key == FieldConfig::Model.field_name()
+            && self.has_model_column()
+            && self.metadata_column(key).is_err()
+        {
+            return Ok(format!("{key} = '{escaped_value}'"));
+        }
+
+        Ok(format!(
+            "meta_{}->>'{}' = '{}'",
+            PgVector::normalize_field_name(key),
+            escaped_key,
+            escaped_value
+        ))
+    }
+
+    /// Splits a `key = value` filter into its key and unquoted value.
+    ///
+    /// The filter is split at the first `=`, so unquoted values may contain `=`. A value starting
+    /// with `"` must end with the matching closing quote; only `\"` and `\\` are escapes inside
+    /// it, and nothing may follow the closing quote.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there is no `=`, the key is empty, or a quoted value is unterminated,
+    /// has an invalid escape, or is followed by other characters.
+    pub(crate) fn parse_filter(filter: &str) -> Result<(&str, String)> {
+        let (key, value) = filter
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Invalid filter format"))?;
+
+        let key = key.trim();
+        if key.is_empty() {
+            return Err(anyhow!("Invalid filter format: missing key"));
+        }
+
+        let value = value.trim();
+        let Some(quoted) = value.strip_prefix('"') else {
+            return Ok((key, value.to_string()));
+        };
+
+        let mut unquoted = String::with_capacity(quoted.len());
+        let mut chars = quoted.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some(escaped @ ('"' | '\\')) => unquoted.push(escaped),
+                    Some(other) => {
+                        return Err(anyhow!("Invalid escape `\\{other}` in filter value"))
+                    }
+                    None => break,
+                },
+                '"' => {
+                    if !chars.as_str().is_empty() {
+                        return Err(anyhow!(
+                            "Unexpected characters after quoted filter value: {}",
+                            chars.as_str()
+                        ));
+                    }
+                    return Ok((key, unquoted));
+                }
+                c => unquoted.push(c),
+            }
+        }
+
+        Err(anyhow!("Unterminated quoted filter value: {value}"))
+    }
+
+    /// Runs a read-only database operation, retrying it on serialization failures.
+    ///
+    /// Retrieves are read-only, so rerunning them after a serialization failure (SQLSTATE
+    /// `40001`) is safe. The operation is attempted up to `retrieve_max_retries` times, waiting
+    /// `retrieve_retry_delay` between attempts. All retrieve queries run through this.
+    async fn retry_on_serialization_failure<T, F, Fut>(&self, mut operation: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, sqlx::Error>>,
+    {
+        let mut attempt = 1;
+        loop {
+            match operation().await {
+                Ok(value) => return Ok(value),
+                Err(sqlx::Error::Database(err))
+                    if attempt < self.retrieve_max_retries
+                        && err.code().as_deref() == Some(SERIALIZATION_FAILURE) =>
+                {
+                    tracing::warn!(
+                        error = %err,
+                        attempt = attempt,
+                        max_retries = self.retrieve_max_retries,
+                        "Retrieve hit a serialization failure, retrying..."
+                    );
+                    attempt += 1;
+                    sleep(self.retrieve_retry_delay).await;
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+
+    /// Returns the relation retrieves read from: `retrieve_from` if set, otherwise the table.
+    fn retrieve_relation(&self) -> &str {
+        self.retrieve_from.as_deref().unwrap_or(&self.table_name)
+    }
+
+    fn has_model_column(&self) -> bool {
+        self.fields
+            .iter()
+            .any(|field| matches!(field, FieldConfig::Model))
+    }
+
+    /// Collects the inputs of a similarity retrieve: the query embedding, the ranked vector
+    /// column, the strategy's filter and `top_k`.
+    ///
+    /// Rows without a vector in the ranked column have no distance to the query, so they are
+    /// excluded.
+    ///
+    /// Returns `None` if the query has no embedding and the [`NoEmbeddingBehavior`] allows it;
+    /// the caller then returns no results, or filter-only results where it supports them.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query has no embedding and the [`NoEmbeddingBehavior`] is `Error`,
+    /// the filter is invalid, no single vector field is configured, or the pool cannot be
+    /// initialized.
+    async fn search_context(
+        &self,
+        search_strategy: &SimilaritySingleEmbedding<String>,
+        query: &Query<states::Pending>,
+    ) -> Result<Option<SearchContext<'_>>> {
+        let Some(embedding) = query.embedding.as_ref() else {
+            return match self.no_embedding_behavior {
+                NoEmbeddingBehavior::Error => Err(anyhow!("Missing embedding in query state")),
+                NoEmbeddingBehavior::FilterOnly | NoEmbeddingBehavior::Empty => Ok(None),
+            };
+        };
+        let embedding = Vector::from(embedding.clone());
+
+        let vector_column = self.get_vector_column_name()?;
+
+        let mut conditions = vec![format!("{vector_column} IS NOT NULL")];
+        if let Some(filter) = search_strategy.filter() {
+            conditions.push(self.filter_condition(filter)?);
+        }
+
+        let top_k = i64::try_from(search_strategy.top_k())
+            .map_err(|_| anyhow!("Failed to convert top_k to i64"))?;
+
+        Ok(Some(SearchContext {
+            pool: self.pool_get_or_initialize().await?,
+            embedding,
+            vector_column,
+            conditions,
+            top_k,
+        }))
+    }
+
+    /// Builds the `ORDER BY` clause ranking by distance to `$1`, then by the options' tie-break.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the tie-break metadata field is not configured.
+    fn retrieve_order_by(
+        &self,
+        vector_column_name: &str,
+        options: &RetrieveOptions,
+    ) -> Result<String> {
+        let mut order_by = format!(" ORDER BY {vector_column_name} <=> $1");
+
+        if let Some((key, order)) = &options.tie_break {
+            order_by.push_str(&format!(
+                ", {}->'{}' {} NULLS LAST",
+                self.metadata_column(key)?,
+                key.replace('\'', "''"),
+                match order {
+                    SortOrder::Ascending => "ASC",
+                    SortOrder::Descending => "DESC",
+                }
+            ));
+        }
+
+        Ok(order_by)
+    }
+
+    /// Retrieves up to `top_k` documents matching the strategy's filter, ignoring similarity.
+    async fn retrieve_filter_only(
+        &self,
+        search_strategy: &SimilaritySingleEmbedding<String>,
+        options: &RetrieveOptions,
+    ) -> Result<Vec<String>> {
+        let pool = self.pool_get_or_initialize().await?;
+
+        let mut conditions = Vec::new();
+        if let Some(filter) = search_strategy.filter() {
+            conditions.push(self.filter_condition(filter)?);
+        }
+        if !options.exclude_ids.is_empty() {
+            conditions.push("id <> ALL($2)".to_string());
+        }
+
+        let sql = format!(
+            "SELECT id, chunk FROM {}{} LIMIT $1",
+            self.retrieve_relation(),
+            where_clause(&conditions)
+        );
+
+        tracing::debug!("Running filter-only retrieve with SQL: {}", sql);
+
+        let top_k = i64::try_from(search_strategy.top_k())
+            .map_err(|_| anyhow!("Failed to convert top_k to i64"))?;
+
+        let data: Vec<VectorSearchResult> = self
+            .retry_on_serialization_failure(|| {
+                options
+                    .bind_exclude_ids(sqlx::query_as(&sql).bind(top_k))
+                    .fetch_all(pool)
+            })
+            .await?;
+
+        Ok(data.into_iter().map(|r| r.chunk).collect())
+    }
+
+    /// Retrieves documents re-ranked with a diversity penalty on a metadata field.
+    ///
+    /// Over-fetches `top_k * candidate_multiplier` candidates with a regular similarity search,
+    /// including the filter, and re-ranks them in Rust. Each repeated value of the metadata field
+    /// adds the penalty to a candidate's distance, demoting later results from the same source.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query has no embedding and the [`NoEmbeddingBehavior`] is `Error`,
+    /// the filter is invalid, the metadata field is not configured, no single vector field is
+    /// configured, or the query fails to execute.
+    pub async fn retrieve_diversified(
         &self,
         search_strategy: &SimilaritySingleEmbedding<String>,
         query_state: Query<states::Pending>,
+        diversity: &DiversityPenalty,
     ) -> Result<Query<states::Retrieved>> {
-        let embedding = if let Some(embedding) = query_state.embedding.as_ref() {
-            Vector::from(embedding.clone())
-        } else {
-            return Err(anyhow::Error::msg("Missing embedding in query state"));
+        let Some(ctx) = self.search_context(search_strategy, &query_state).await? else {
+            return Ok(query_state.retrieved_documents(Vec::new()));
         };
+        let metadata_column = self.metadata_column(&diversity.metadata_field)?;
 
-        let vector_column_name = self.get_vector_column_name()?;
+        let sql = format!(
+            "SELECT chunk, {metadata_column}->>$3 AS value, {} <=> $1 AS distance FROM {}{} \
+             ORDER BY distance LIMIT $2",
+            ctx.vector_column,
+            self.retrieve_relation(),
+            ctx.where_clause()
+        );
 
-        let pool = self.pool_get_or_initialize().await?;
+        tracing::debug!("Running diversified retrieve with SQL: {}", sql);
 
-        let default_columns: Vec<_> = PgVectorBuilder::default_fields()
+        let top_k = usize::try_from(search_strategy.top_k())
+            .map_err(|_| anyhow!("Failed to convert top_k to usize"))?;
+        let candidates = i64::try_from(
+            search_strategy
+                .top_k()
+                .saturating_mul(diversity.candidate_multiplier),
+        )
+        .map_err(|_| anyhow!("Failed to convert candidate count to i64"))?;
+
+        let rows: Vec<(String, Option<String>, f64)> = self
+            .retry_on_serialization_failure(|| {
+                sqlx::query_as(&sql)
+                    .bind(ctx.embedding.clone())
+                    .bind(candidates)
+                    .bind(&diversity.metadata_field)
+                    .fetch_all(ctx.pool)
+            })
+            .await?;
+
+        let docs = diversity.rerank(rows, top_k);
+
+        Ok(query_state.retrieved_documents(docs))
+    }
+
+    /// Retrieves documents by similarity, filtered and boosted by a [`BoolQuery`].
+    ///
+    /// `must` and `must_not` clauses are combined with the strategy's filter in the `WHERE`
+    /// clause. Results are ordered by their cosine distance minus the boosts of all matching
+    /// `should` clauses.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a boost is not finite, the query has no embedding and the
+    /// [`NoEmbeddingBehavior`] is `Error`, the filter is invalid, a clause refers to a metadata
+    /// field that is not configured, no single vector field is configured, or the query fails to
+    /// execute.
+    pub async fn retrieve_bool(
+        &self,
+        search_strategy: &SimilaritySingleEmbedding<String>,
+        query_state: Query<states::Pending>,
+        bool_query: &BoolQuery,
+    ) -> Result<Query<states::Retrieved>> {
+        if let Some((key, value, boost)) = bool_query
+            .should
             .iter()
-            .map(|f| f.field_name().to_string())
-            .collect();
+            .find(|(_, _, boost)| !boost.is_finite())
+        {
+            return Err(anyhow!(
+                "Boost of should clause `{key} = {value}` must be finite, got {boost}"
+            ));
+        }
+
+        let Some(mut ctx) = self.search_context(search_strategy, &query_state).await? else {
+            return Ok(query_state.retrieved_documents(Vec::new()));
+        };
+
+        // Clause keys and values are bound after the embedding ($1) and top_k ($2)
+        let mut binds: Vec<String> = Vec::new();
+        let mut clause = |key: &str, value: &str| -> Result<String> {
+            let column = self.metadata_column(key)?;
+            binds.push(key.to_string());
+            binds.push(value.to_string());
+            Ok(format!(
+                "{column}->>${} = ${}",
+                binds.len() + 1,
+                binds.len() + 2
+            ))
+        };
+
+        for (key, value) in &bool_query.must {
+            ctx.conditions.push(clause(key, value)?);
+        }
+        for (key, value) in &bool_query.must_not {
+            // Rows without the field do not match, so they are kept
+            ctx.conditions
+                .push(format!("({}) IS NOT TRUE", clause(key, value)?));
+        }
+
+        let mut boosts = Vec::new();
+        for (key, value, boost) in &bool_query.should {
+            boosts.push(format!(
+                "CASE WHEN {} THEN {boost}::float8 ELSE 0 END",
+                clause(key, value)?
+            ));
+        }
+
+        let mut sql = format!(
+            "SELECT id, chunk FROM {}{} ORDER BY ({} <=> $1)",
+            self.retrieve_relation(),
+            ctx.where_clause(),
+            ctx.vector_column
+        );
+        for boost in &boosts {
+            sql.push_str(&format!(" - {boost}"));
+        }
+        sql.push_str(" LIMIT $2");
+
+        tracing::debug!("Running bool query retrieve with SQL: {}", sql);
+
+        let data: Vec<VectorSearchResult> = self
+            .retry_on_serialization_failure(|| {
+                let mut query = sqlx::query_as(&sql)
+                    .bind(ctx.embedding.clone())
+                    .bind(ctx.top_k);
+                for bind in &binds {
+                    query = query.bind(bind);
+                }
+                query.fetch_all(ctx.pool)
+            })
+            .await?;
+
+        Ok(query_state.retrieved_documents(data.into_iter().map(|r| r.chunk).collect()))
+    }
+
+    /// Retrieves the top results for a query grouped by their parent document.
+    ///
+    /// The parent of each chunk is read from the `parent_field` metadata field; chunks without
+    /// it, stored through an optional [`crate::pgvector::MetadataConfig`], are not retrieved.
+    /// The `top_k` closest children are retrieved and nested under their parent. Children are
+    /// ordered by distance within each parent, and parents by their closest child.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query has no embedding and the [`NoEmbeddingBehavior`] is `Error`,
+    /// the filter is invalid, the parent field is not configured, no single vector field is
+    /// configured, or the query fails to execute.
+    pub async fn retrieve_grouped_by_parent(
+        &self,
+        search_strategy: &SimilaritySingleEmbedding<String>,
+        query: &Query<states::Pending>,
+        parent_field: &str,
+    ) -> Result<Vec<ParentGroup>> {
+        let Some(mut ctx) = self.search_context(search_strategy, query).await? else {
+            return Ok(Vec::new());
+        };
+        let parent_column = self.metadata_column(parent_field)?;
+
+        ctx.conditions
+            .push(format!("{parent_column}->>$3 IS NOT NULL"));
+
+        let sql = format!(
+            "SELECT chunk, {parent_column}->>$3 AS parent_id, {} <=> $1 AS distance FROM {}{} \
+             ORDER BY distance LIMIT $2",
+            ctx.vector_column,
+            self.retrieve_relation(),
+            ctx.where_clause()
+        );
+
+        tracing::debug!("Running grouped retrieve with SQL: {}", sql);
+
+        let rows: Vec<(String, String, f64)> = self
+            .retry_on_serialization_failure(|| {
+                sqlx::query_as(&sql)
+                    .bind(ctx.embedding.clone())
+                    .bind(ctx.top_k)
+                    .bind(parent_field)
+                    .fetch_all(ctx.pool)
+            })
+            .await?;
+
+        // Rows are ordered by distance, so groups and their children keep that order
+        let mut groups: Vec<ParentGroup> = Vec::new();
+        for (chunk, parent_id, distance) in rows {
+            let child = ChildChunk { chunk, distance };
+            match groups.iter_mut().find(|group| group.parent_id == parent_id) {
+                Some(group) => group.children.push(child),
+                None => groups.push(ParentGroup {
+                    parent_id,
+                    children: vec![child],
+                }),
+            }
+        }
+
+        Ok(groups)
+    }
+
+    /// Retrieves the top results for a query as citations into their source documents.
+    ///
+    /// The source and character offsets are read from the metadata fields configured in
+    /// `citation`. Results are ordered by similarity.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query has no embedding and the [`NoEmbeddingBehavior`] is `Error`,
+    /// the filter is invalid, a citation field is not configured, a result lacks a citation field
+    /// or has invalid offsets, or the query fails to execute.
+    pub async fn retrieve_citations(
+        &self,
+        search_strategy: &SimilaritySingleEmbedding<String>,
+        query: &Query<states::Pending>,
+        citation: &CitationConfig,
+    ) -> Result<Vec<Citation>> {
+        let Some(ctx) = self.search_context(search_strategy, query).await? else {
+            return Ok(Vec::new());
+        };
+        let source_column = self.metadata_column(&citation.source_field)?;
+        let start_column = self.metadata_column(&citation.start_field)?;
+        let end_column = self.metadata_column(&citation.end_field)?;
+
+        let sql = format!(
+            "SELECT chunk, {source_column}->>$3, {start_column}->>$4, {end_column}->>$5 FROM {}{} \
+             ORDER BY {} <=> $1 LIMIT $2",
+            self.retrieve_relation(),
+            ctx.where_clause(),
+            ctx.vector_column
+        );
+
+        tracing::debug!("Running citation retrieve with SQL: {}", sql);
+
+        let rows: Vec<(String, Option<String>, Option<String>, Option<String>)> = self
+            .retry_on_serialization_failure(|| {
+                sqlx::query_as(&sql)
+                    .bind(ctx.embedding.clone())
+                    .bind(ctx.top_k)
+                    .bind(&citation.source_field)
+                    .bind(&citation.start_field)
+                    .bind(&citation.end_field)
+                    .fetch_all(ctx.pool)
+            })
+            .await?;
+
+        rows.into_iter()
+            .map(|(text, source, start, end)| {
+                let field = |value: Option<String>, key: &str| {
+                    value.ok_or_else(|| anyhow!("Missing citation field `{key}` for chunk: {text}"))
+                };
+                let offset = |value: Option<String>, key: &str| {
+                    field(value, key)?
+                        .parse::<usize>()
+                        .map_err(|err| anyhow!("Invalid citation offset `{key}`: {err}"))
+                };
+
+                let source = field(source, &citation.source_field)?;
+                let start = offset(start, &citation.start_field)?;
+                let end = offset(end, &citation.end_field)?;
+
+                if start > end {
+                    return Err(anyhow!(
+                        "Citation start {start} is after end {end} in {source}"
+                    ));
+                }
+
+                Ok(Citation {
+                    source,
+                    start,
+                    end,
+                    text,
+                })
+            })
+            .collect()
+    }
+
+    /// Retrieves the top results for a query together with their pairwise distance matrix.
+    ///
+    /// Runs a regular similarity retrieve, including the filter, additionally fetching the
+    /// vectors of the results. The cosine distances between all results are computed in Rust,
+    /// which is useful to analyze or visualize how the results cluster.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query has no embedding and the [`NoEmbeddingBehavior`] is `Error`,
+    /// the filter is invalid, no single vector field is configured, or the query fails to execute.
+    pub async fn retrieve_with_distance_matrix(
+        &self,
+        search_strategy: &SimilaritySingleEmbedding<String>,
+        query: &Query<states::Pending>,
+    ) -> Result<DocumentsWithDistances> {
+        let Some(ctx) = self.search_context(search_strategy, query).await? else {
+            return Ok(DocumentsWithDistances {
+                documents: Vec::new(),
+                distances: Vec::new(),
+            });
+        };
+
+        let sql = format!(
+            "SELECT chunk, {column} FROM {}{} ORDER BY {column} <=> $1 LIMIT $2",
+            self.retrieve_relation(),
+            ctx.where_clause(),
+            column = ctx.vector_column
+        );
+
+        tracing::debug!("Running retrieve with distance matrix with SQL: {}", sql);
+
+        let rows: Vec<(String, Vector)> = self
+            .retry_on_serialization_failure(|| {
+                sqlx::query_as(&sql)
+                    .bind(ctx.embedding.clone())
+                    .bind(ctx.top_k)
+                    .fetch_all(ctx.pool)
+            })
+            .await?;
+
+        let (documents, vectors): (Vec<String>, Vec<Vec<f32>>) = rows
+            .into_iter()
+            .map(|(chunk, vector)| (chunk, vector.to_vec()))
+            .unzip();
+
+        Ok(DocumentsWithDistances {
+            documents,
+            distances: cosine_distance_matrix(&vectors),
+        })
+    }
+
+    /// Retrieves the top results for a query, each together with its own nearest neighbors.
+    ///
+    /// The top results are selected like a regular similarity retrieve, including the filter.
+    /// For each result, its `neighbor_k` nearest neighbors are looked up with a LATERAL join,
+    /// excluding the result itself. Neighbors are not filtered.
+    ///
+    /// # Arguments
+    ///
+    /// * `search_strategy` - The similarity search strategy, providing `top_k` and the filter.
+    /// * `query` - The query, which must have an embedding.
+    /// * `neighbor_k` - The number of neighbors to return per result.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query has no embedding and the [`NoEmbeddingBehavior`] is `Error`,
+    /// the filter is invalid, no single vector field is configured, or the query fails to execute.
+    pub async fn retrieve_with_neighbors(
+        &self,
+        search_strategy: &SimilaritySingleEmbedding<String>,
+        query: &Query<states::Pending>,
+        neighbor_k: u64,
+    ) -> Result<Vec<DocumentWithNeighbors>> {
+        let Some(ctx) = self.search_context(search_strategy, query).await? else {
+            return Ok(Vec::new());
+        };
+
+        // Rows without a vector have no distance, so they are never neighbors either
+        let sql = format!(
+            r#"
+            WITH matches AS (
+                SELECT id, chunk, {column} AS embedding, {column} <=> $1 AS distance
+                FROM {table}{where_clause}
+                ORDER BY distance LIMIT $2
+            )
+            SELECT m.id, m.chunk, n.chunk AS neighbor_chunk
+            FROM matches m
+            LEFT JOIN LATERAL (
+                SELECT c.chunk, c.{column} <=> m.embedding AS distance
+                FROM {table} c
+                WHERE c.id <> m.id AND c.{column} IS NOT NULL
+                ORDER BY distance LIMIT $3
+            ) n ON true
+            ORDER BY m.distance, m.id, n.distance"#,
+            column = ctx.vector_column,
+            table = self.retrieve_relation(),
+            where_clause = ctx.where_clause()
+        );
+
+        tracing::debug!("Running retrieve with neighbors with SQL: {}", sql);
+
+        let neighbor_k = i64::try_from(neighbor_k)
+            .map_err(|_| anyhow!("Failed to convert neighbor_k to i64"))?;
+
+        let rows: Vec<(Uuid, String, Option<String>)> = self
+            .retry_on_serialization_failure(|| {
+                sqlx::query_as(&sql)
+                    .bind(ctx.embedding.clone())
+                    .bind(ctx.top_k)
+                    .bind(neighbor_k)
+                    .fetch_all(ctx.pool)
+            })
+            .await?;
+
+        // Rows are ordered by match, so neighbors of the same match are consecutive
+        let mut results: Vec<(Uuid, DocumentWithNeighbors)> = Vec::new();
+        for (id, chunk, neighbor) in rows {
+            if !results.last().is_some_and(|(last_id, _)| *last_id == id) {
+                results.push((
+                    id,
+                    DocumentWithNeighbors {
+                        document: chunk,
+                        neighbors: Vec::new(),
+                    },
+                ));
+            }
+
+            if let (Some(neighbor), Some((_, result))) = (neighbor, results.last_mut()) {
+                result.neighbors.push(neighbor);
+            }
+        }
+
+        Ok(results.into_iter().map(|(_, result)| result).collect())
+    }
+
+    /// Retrieves all documents within a cosine distance `radius` of `embedding`.
+    ///
+    /// Unlike a top-k search, every row under the distance threshold is returned, ordered by
+    /// distance. The number of results is capped by `radius_search_limit` as a safety measure.
+    ///
+    /// # Arguments
+    ///
+    /// * `embedding` - The vector to search around.
+    /// * `radius` - The maximum cosine distance (inclusive) of returned rows.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(Vec<String>)` - The chunks of all matching rows, closest first.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the radius is negative, no single vector field is configured, or the
+    /// query fails to execute.
+    pub async fn retrieve_within_radius(
+        &self,
+        embedding: &[f32],
+        radius: f64,
+    ) -> Result<Vec<String>> {
+        if radius.is_nan() || radius < 0.0 {
+            return Err(anyhow!("Radius must be a non-negative number"));
+        }
+
+        let vector_column_name = self.get_vector_column_name()?;
+        let pool = self.pool_get_or_initialize().await?;
+
+        let limit = i64::try_from(self.radius_search_limit)
+            .map_err(|_| anyhow!("Failed to convert radius_search_limit to i64"))?;
+
+        let sql = format!(
+            "SELECT id, chunk FROM {} WHERE {vector_column_name} <=> $1 <= $2 ORDER BY {vector_column_name} <=> $1 LIMIT $3",
+            self.retrieve_relation()
+        );
+
+        tracing::debug!("Running radius retrieve with SQL: {}", sql);
+
+        let embedding = Vector::from(embedding.to_vec());
+        let data: Vec<VectorSearchResult> = self
+            .retry_on_serialization_failure(|| {
+                sqlx::query_as(&sql)
+                    .bind(embedding.clone())
+                    .bind(radius)
+                    .bind(limit)
+                    .fetch_all(pool)
+            })
+            .await?;
+
+        if data.len() == self.radius_search_limit {
+            tracing::warn!(
+                limit = self.radius_search_limit,
+                "Radius search reached the result limit; results may be truncated"
+            );
+        }
+
+        Ok(data.into_iter().map(|r| r.chunk).collect())
+    }
+
+    /// Retrieves documents matching a full-text query in the given language.
+    ///
+    /// The query is parsed with `plainto_tsquery` using `language` as the text search
+    /// configuration, and results are ranked with `ts_rank`. When a per-row language column is
+    /// configured, only rows indexed in that language are searched; rows with a missing or
+    /// unknown language count as the default language.
+    ///
+    /// # Arguments
+    ///
+    /// * `query` - The plain text query.
+    /// * `language` - The text search configuration, e.g. `english` or `spanish`.
+    /// * `top_k` - The maximum number of documents to return.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if full-text search is not configured, the language is invalid, or the
+    /// query fails to execute.
+    pub async fn retrieve_fulltext(
+        &self,
+        query: &str,
+        language: &str,
+        top_k: u64,
+    ) -> Result<Vec<String>> {
+        let config = self
+            .fields
+            .iter()
+            .find_map(|field| match field {
+                FieldConfig::FullText(config) => Some(config),
+                _ => None,
+            })
+            .ok_or_else(|| anyhow!("Full-text search is not configured"))?;
+
+        if !PgVector::is_valid_identifier(language) {
+            return Err(anyhow!("Invalid full-text language: {language}"));
+        }
+
+        let pool = self.pool_get_or_initialize().await?;
+
+        let mut sql = format!(
+            "SELECT id, chunk FROM {} WHERE chunk_tsv @@ plainto_tsquery($1::regconfig, $2)",
+            self.retrieve_relation()
+        );
+
+        // Match the language the tsvector was built with, so unknown languages count as default
+        if config.language_metadata_key.is_some() {
+            sql.push_str(&format!(
+                " AND COALESCE(to_regconfig(language), '{}'::regconfig) = $1::regconfig",
+                config.default_language()?
+            ));
+        }
+
+        sql.push_str(
+            " ORDER BY ts_rank(chunk_tsv, plainto_tsquery($1::regconfig, $2)) DESC LIMIT $3",
+        );
+
+        tracing::debug!("Running full-text retrieve with SQL: {}", sql);
+
+        let top_k = i64::try_from(top_k).map_err(|_| anyhow!("Failed to convert top_k to i64"))?;
+
+        let data: Vec<VectorSearchResult> = self
+            .retry_on_serialization_failure(|| {
+                sqlx::query_as(&sql)
+                    .bind(language)
+                    .bind(query)
+                    .bind(top_k)
+                    .fetch_all(pool)
+            })
+            .await?;
+
+        Ok(data.into_iter().map(|r| r.chunk).collect())
+    }
+
+    /// Retrieves rows by similarity, returning only the columns selected by the options'
+    /// [`Projection`].
+    ///
+    /// Rows are selected like [`PgVector::retrieve_with_options`], including the filter and
+    /// excluded IDs, but e.g. the chunk can be left out when only the score and some metadata
+    /// are needed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the query has no embedding and the [`NoEmbeddingBehavior`] is `Error`,
+    /// the filter is invalid, a projected metadata field is not configured, no single vector field
+    /// is configured, or the query fails to execute.
+    pub async fn retrieve_projected(
+        &self,
+        search_strategy: &SimilaritySingleEmbedding<String>,
+        query: &Query<states::Pending>,
+        options: &RetrieveOptions,
+    ) -> Result<Vec<ProjectedRow>> {
+        let Some(mut ctx) = self.search_context(search_strategy, query).await? else {
+            return Ok(Vec::new());
+        };
+        ctx.exclude_ids(options, 3);
+
+        let projection = options.projection.clone().unwrap_or_else(Projection::all);
+
+        let metadata_columns = projection
+            .metadata
+            .iter()
+            .map(|key| Ok((key.as_str(), self.metadata_column(key)?)))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut columns = Vec::new();
+        if projection.id {
+            columns.push("id".to_string());
+        }
+        if projection.chunk {
+            columns.push("chunk".to_string());
+        }
+        if projection.score {
+            columns.push(format!(
+                "(1 - ({} <=> $1))::float8 AS score",
+                ctx.vector_column
+            ));
+        }
+        columns.extend(
+            metadata_columns
+                .iter()
+                .map(|(_, column)| (*column).to_string()),
+        );
+
+        // Postgres requires a select list, select a constant when nothing is projected
+        if columns.is_empty() {
+            columns.push("1".to_string());
+        }
+
+        let sql = format!(
+            "SELECT {} FROM {}{}{} LIMIT $2",
+            columns.join(", "),
+            self.retrieve_relation(),
+            ctx.where_clause(),
+            self.retrieve_order_by(&ctx.vector_column, options)?
+        );
+
+        tracing::debug!("Running projected retrieve with SQL: {}", sql);
+
+        let rows = self
+            .retry_on_serialization_failure(|| {
+                let mut query = sqlx::query(&sql)
+                    .bind(ctx.embedding.clone())
+                    .bind(ctx.top_k);
+                if !options.exclude_ids.is_empty() {
+                    query = query.bind(&options.exclude_ids);
+                }
+                query.fetch_all(ctx.pool)
+            })
+            .await?;
+
+        rows.iter()
+            .map(|row| {
+                let mut metadata = HashMap::new();
+                for (key, column) in &metadata_columns {
+                    let value: Option<serde_json::Value> = row.try_get(*column)?;
+                    if let Some(value) = value.as_ref().and_then(|value| value.get(key)) {
+                        metadata.insert((*key).to_string(), value.clone());
+                    }
+                }
+
+                Ok(ProjectedRow {
+                    id: projection.id.then(|| row.try_get("id")).transpose()?,
+                    chunk: projection.chunk.then(|| row.try_get("chunk")).transpose()?,
+                    score: projection.score.then(|| row.try_get("score")).transpose()?,
+                    metadata,
+                })
+            })
+            .collect()
+    }
+
+    /// Retrieves documents by similarity like [`Retrieve::retrieve`], applying per-query options.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`Retrieve::retrieve`]: a missing embedding (depending on the
+    /// [`NoEmbeddingBehavior`]), an invalid filter, no single vector field, or a failing query.
+    #[allow(clippy::redundant_closure_for_method_calls)]
+    pub async fn retrieve_with_options(
+        &self,
+        search_strategy: &SimilaritySingleEmbedding<String>,
+        query_state: Query<states::Pending>,
+        options: &RetrieveOptions,
+    ) -> Result<Query<states::Retrieved>> {
+        let Some(mut ctx) = self.search_context(search_strategy, &query_state).await? else {
+            let docs = match self.no_embedding_behavior {
+                NoEmbeddingBehavior::FilterOnly => {
+                    self.retrieve_filter_only(search_strategy, options).await?
+                }
+                NoEmbeddingBehavior::Error | NoEmbeddingBehavior::Empty => Vec::new(),
+            };
+            return Ok(query_state.retrieved_documents(docs));
+        };
+        ctx.exclude_ids(options, 3);
+
+        let default_columns: Vec<_> = PgVectorBuilder::default_fields()
+            .iter()
+            .map(|f| f.field_name().to_string())
+            .collect();
+
+        // Start building the SQL query
+        let mut sql = format!(
+            "SELECT {} FROM {}{}",
+            default_columns.join(", "),
+            self.retrieve_relation(),
+            ctx.where_clause()
+        );
+
+        // Add the ORDER BY clause for vector similarity search
+        sql.push_str(&self.retrieve_order_by(&ctx.vector_column, options)?);
+        sql.push_str(" LIMIT $2");
+
+        tracing::debug!("Running retrieve with SQL: {}", sql);
+
+        let data: Vec<VectorSearchResult> = self
+            .retry_on_serialization_failure(|| {
+                let query = sqlx::query_as(&sql)
+                    .bind(ctx.embedding.clone())
+                    .bind(ctx.top_k);
+                options.bind_exclude_ids(query).fetch_all(ctx.pool)
+            })
+            .await?;
+
+        let docs = data.into_iter().map(|r| r.chunk).collect();
+
+        Ok(query_state.retrieved_documents(docs))
+    }
+}
+
+#[async_trait]
+impl Retrieve<SimilaritySingleEmbedding<String>> for PgVector {
+    #[tracing::instrument]
+    async fn retrieve(
+        &self,
+        search_strategy: &SimilaritySingleEmbedding<String>,
+        query_state: Query<states::Pending>,
+    ) -> Result<Query<states::Retrieved>> {
+        self.retrieve_with_options(search_strategy, query_state, &RetrieveOptions::default())
+            .await
+    }
+}
+
+/// Computes the symmetric matrix of cosine distances between all vectors.
+///
+/// The diagonal is zero. Distances involving a zero vector are `1.0`.
+fn cosine_distance_matrix(vectors: &[Vec<f32>]) -> Vec<Vec<f64>> {
+    let norms: Vec<f64> = vectors
+        .iter()
+        .map(|v| v.iter().map(|x| f64::from(*x).powi(2)).sum::<f64>().sqrt())
+        .collect();
+
+    let mut distances = vec![vec![0.0; vectors.len()]; vectors.len()];
+    for i in 0..vectors.len() {
+        for j in (i + 1)..vectors.len() {
+            let dot: f64 = vectors[i]
+                .iter()
+                .zip(&vectors[j])
+                .map(|(a, b)| f64::from(*a) * f64::from(*b))
+                .sum();
+
+            let distance = if norms[i] == 0.0 || norms[j] == 0.0 {
+                1.0
+            } else {
+                1.0 - dot / (norms[i] * norms[j])
+            };
+
+            distances[i][j] = distance;
+            distances[j][i] = distance;
+        }
+    }
+
+    distances
+}
+
+#[async_trait]
+impl Retrieve<SimilaritySingleEmbedding> for PgVector {
+    async fn retrieve(
+        &self,
+        search_strategy: &SimilaritySingleEmbedding,
+        query: Query<states::Pending>,
+    ) -> Result<Query<states::Retrieved>> {
+        Retrieve::<SimilaritySingleEmbedding<String>>::retrieve(
+            self,
+            &search_strategy.into_concrete_filter::<String>(),
+            query,
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pgvector::{
+        fixtures::TestContext, BoolQuery, Citation, CitationConfig, DiversityPenalty,
+        FullTextConfig, MetadataConfig, NoEmbeddingBehavior, PgVector, Projection, RetrieveOptions,
+        SortOrder,
+    };
+    use futures_util::TryStreamExt;
+    use std::collections::HashSet;
+    use swiftide_core::{indexing, indexing::EmbeddedField, Persist};
+    use swiftide_core::{
+        querying::{search_strategies::SimilaritySingleEmbedding, states, Query},
+        Retrieve,
+    };
+
+    #[test_log::test(tokio::test)]
+    async fn test_retrieve_multiple_docs_and_filter() {
+        let test_context = TestContext::setup_with_cfg(
+            vec!["filter"].into(),
+            HashSet::from([EmbeddedField::Combined]),
+        )
+        .await
+        .expect("Test setup failed");
+
+        let nodes = vec![
+            indexing::Node::new("test_query1").with_metadata(("filter", "true")),
+            indexing::Node::new("test_query2").with_metadata(("filter", "true")),
+            indexing::Node::new("test_query3").with_metadata(("filter", "false")),
+        ]
+        .into_iter()
+        .map(|node| {
+            node.with_vectors([(EmbeddedField::Combined, vec![1.0; 384])]);
+            node.to_owned()
+        })
+        .collect();
+
+        test_context
+            .pgv_storage
+            .batch_store(nodes)
+            .await
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        let mut query = Query::<states::Pending>::new("test_query");
+        query.embedding = Some(vec![1.0; 384]);
+
+        let search_strategy = SimilaritySingleEmbedding::<()>::default();
+        let result = test_context
+            .pgv_storage
+            .retrieve(&search_strategy, query.clone())
+            .await
+            .unwrap();
+
+        assert_eq!(result.documents().len(), 3);
+
+        let search_strategy =
+            SimilaritySingleEmbedding::from_filter("filter = \"true\"".to_string());
+
+        let result = test_context
+            .pgv_storage
+            .retrieve(&search_strategy, query.clone())
+            .await
+            .unwrap();
+
+        assert_eq!(result.documents().len(), 2);
+
+        let search_strategy =
+            SimilaritySingleEmbedding::from_filter("filter = \"banana\"".to_string());
+
+        let result = test_context
+            .pgv_storage
+            .retrieve(&search_strategy, query.clone())
+            .await
+            .unwrap();
+        assert_eq!(result.documents().len(), 0);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_retrieve_no_match_filter_is_empty_success() {
+        let test_context = TestContext::setup_with_cfg(
+            vec!["filter"].into(),
+            HashSet::from([EmbeddedField::Combined]),
+        )
+        .await
+        .expect("Test setup failed");
+
+        let node = indexing::Node::new("test_query")
+            .with_metadata(("filter", "true"))
+            .with_vectors([(EmbeddedField::Combined, vec![1.0; 384])])
+            .to_owned();
+        test_context.pgv_storage.store_nodes(&[node]).await.unwrap();
+
+        let mut query = Query::<states::Pending>::new("test_query");
+        query.embedding = Some(vec![1.0; 384]);
+
+        let search_strategy =
+            SimilaritySingleEmbedding::from_filter("filter = \"banana\"".to_string());
+
+        let result = test_context
+            .pgv_storage
+            .retrieve(&search_strategy, query)
+            .await
+            .expect("A filter without matches should not be an error");
+        assert!(result.is_empty());
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_retrieve_within_radius() {
+        let test_context = TestContext::setup_with_cfg(
+            vec!["filter"].into(),
+            HashSet::from([EmbeddedField::Combined]),
+        )
+        .await
+        .expect("Test setup failed");
+
+        let mut near_vector = vec![1.0; 384];
+        near_vector[0] = 0.0;
+
+        let nodes = vec![
+            indexing::Node::new("exact")
+                .with_metadata(("filter", "true"))
+                .with_vectors([(EmbeddedField::Combined, vec![1.0; 384])])
+                .to_owned(),
+            indexing::Node::new("near")
+                .with_metadata(("filter", "true"))
+                .with_vectors([(EmbeddedField::Combined, near_vector)])
+                .to_owned(),
+            indexing::Node::new("opposite")
+                .with_metadata(("filter", "false"))
+                .with_vectors([(EmbeddedField::Combined, vec![-1.0; 384])])
+                .to_owned(),
+        ];
+
+        test_context
+            .pgv_storage
+            .batch_store(nodes)
+            .await
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        let result = test_context
+            .pgv_storage
+            .retrieve_within_radius(&[1.0; 384], 0.5)
+            .await
+            .unwrap();
+
+        assert_eq!(result, vec!["exact".to_string(), "near".to_string()]);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_retrieve_fulltext_generated_column() {
+        let test_context = TestContext::setup_with_builder(
+            None,
+            HashSet::from([EmbeddedField::Combined]),
+            |builder| builder.with_fulltext_search(FullTextConfig::new("english").generated()),
+        )
+        .await
+        .expect("Test setup failed");
+
+        let pool = test_context.pgv_storage.get_pool().await.unwrap();
+        let generated: String = sqlx::query_scalar(
+            "SELECT is_generated FROM information_schema.columns \
+             WHERE table_name = 'swiftide_pgvector_test' AND column_name = 'chunk_tsv'",
+        )
+        .fetch_one(pool)
+        .await
+        .unwrap();
+        assert_eq!(generated, "ALWAYS");
+
+        let node = indexing::Node::new("The cats are running quickly")
+            .with_vectors([(EmbeddedField::Combined, vec![1.0; 384])])
+            .to_owned();
+
+        test_context.pgv_storage.store_nodes(&[node]).await.unwrap();
+
+        let tsv: Option<String> =
+            sqlx::query_scalar("SELECT chunk_tsv::text FROM swiftide_pgvector_test")
+                .fetch_one(pool)
+                .await
+                .unwrap();
+        assert!(tsv.is_some_and(|tsv| tsv.contains("'cat'")));
+
+        let result = test_context
+            .pgv_storage
+            .retrieve_fulltext("cat runs", "english", 10)
+            .await
+            .unwrap();
+        assert_eq!(result, vec!["The cats are running quickly".to_string()]);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_retrieve_fulltext_per_row_language() {
+        let test_context = TestContext::setup_with_builder(
+            None,
+            HashSet::from([EmbeddedField::Combined]),
+            |builder| {
+                builder.with_fulltext_search(
+                    FullTextConfig::new("simple").with_language_metadata("language"),
+                )
+            },
+        )
+        .await
+        .expect("Test setup failed");
+
+        let nodes = vec![
+            indexing::Node::new("The cats are running quickly")
+                .with_metadata(("language", "english"))
+                .with_vectors([(EmbeddedField::Combined, vec![1.0; 384])])
+                .to_owned(),
+            indexing::Node::new("Los gatos están corriendo rápidamente")
+                .with_metadata(("language", "spanish"))
+                .with_vectors([(EmbeddedField::Combined, vec![1.0; 384])])
+                .to_owned(),
+        ];
+
+        test_context
+            .pgv_storage
+            .batch_store(nodes)
+            .await
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        // English stemming matches "cats running"
+        let result = test_context
+            .pgv_storage
+            .retrieve_fulltext("cat runs", "english", 10)
+            .await
+            .unwrap();
+        assert_eq!(result, vec!["The cats are running quickly".to_string()]);
+
+        // Spanish stemming matches "gatos"
+        let result = test_context
+            .pgv_storage
+            .retrieve_fulltext("gato", "spanish", 10)
+            .await
+            .unwrap();
+        assert_eq!(
+            result,
+            vec!["Los gatos están corriendo rápidamente".to_string()]
+        );
+
+        // Spanish documents are not searched with the English configuration
+        let result = test_context
+            .pgv_storage
+            .retrieve_fulltext("gato", "english", 10)
+            .await
+            .unwrap();
+        assert!(result.is_empty());
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_store_fulltext_unknown_language_uses_default() {
+        let test_context = TestContext::setup_with_builder(
+            None,
+            HashSet::from([EmbeddedField::Combined]),
+            |builder| {
+                builder.with_fulltext_search(
+                    FullTextConfig::new("simple").with_language_metadata("language"),
+                )
+            },
+        )
+        .await
+        .expect("Test setup failed");
+
+        let nodes = vec![
+            indexing::Node::new("The cats are running quickly")
+                .with_metadata(("language", "english"))
+                .with_vectors([(EmbeddedField::Combined, vec![1.0; 384])])
+                .to_owned(),
+            indexing::Node::new("nuqneH tlhIngan")
+                .with_metadata(("language", "klingon"))
+                .with_vectors([(EmbeddedField::Combined, vec![1.0; 384])])
+                .to_owned(),
+            indexing::Node::new("invalid language name")
+                .with_metadata(("language", "not a language!"))
+                .with_vectors([(EmbeddedField::Combined, vec![1.0; 384])])
+                .to_owned(),
+        ];
+
+        test_context
+            .pgv_storage
+            .batch_store(nodes)
+            .await
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        // The unknown language is indexed with the default `simple` configuration
+        let result = test_context
+            .pgv_storage
+            .retrieve_fulltext("tlhIngan", "simple", 10)
+            .await
+            .unwrap();
+        assert_eq!(result, vec!["nuqneH tlhIngan".to_string()]);
+
+        let result = test_context
+            .pgv_storage
+            .retrieve_fulltext("cat", "english", 10)
+            .await
+            .unwrap();
+        assert_eq!(result, vec!["The cats are running quickly".to_string()]);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_retrieve_with_neighbors() {
+        let test_context = TestContext::setup_with_cfg(
+            vec!["filter"].into(),
+            HashSet::from([EmbeddedField::Combined]),
+        )
+        .await
+        .expect("Test setup failed");
+
+        let nodes = (0..5)
+            .map(|i| {
+                indexing::Node::new(format!("neighbor_{i}"))
+                    .with_metadata(("filter", "true"))
+                    .with_vectors([(EmbeddedField::Combined, vec![1.0; 384])])
+                    .to_owned()
+            })
+            .collect();
+
+        test_context
+            .pgv_storage
+            .batch_store(nodes)
+            .await
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        let mut query = Query::<states::Pending>::new("test_query");
+        query.embedding = Some(vec![1.0; 384]);
+
+        let mut search_strategy = SimilaritySingleEmbedding::<String>::default();
+        search_strategy.with_top_k(2);
+
+        let results = test_context
+            .pgv_storage
+            .retrieve_with_neighbors(&search_strategy, &query, 3)
+            .await
+            .unwrap();
+
+        assert_eq!(results.len(), 2);
+        for result in &results {
+            assert_eq!(result.neighbors.len(), 3);
+            assert!(!result.neighbors.contains(&result.document));
+        }
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_retrieve_with_neighbors_skips_rows_without_vector() {
+        let test_context =
+            TestContext::setup_with_cfg(None, HashSet::from([EmbeddedField::Combined]))
+                .await
+                .expect("Test setup failed");
+
+        let nodes: Vec<_> = ["first", "second"]
+            .into_iter()
+            .map(|chunk| {
+                indexing::Node::new(chunk)
+                    .with_vectors([(EmbeddedField::Combined, vec![1.0; 384])])
+                    .to_owned()
+            })
+            .collect();
+        test_context.pgv_storage.store_nodes(&nodes).await.unwrap();
+
+        let pool = test_context.pgv_storage.get_pool().await.unwrap();
+        sqlx::query(
+            "INSERT INTO swiftide_pgvector_test (id, chunk) VALUES (gen_random_uuid(), 'no vector')",
+        )
+        .execute(pool)
+        .await
+        .unwrap();
+
+        let mut query = Query::<states::Pending>::new("test_query");
+        query.embedding = Some(vec![1.0; 384]);
+
+        let mut search_strategy = SimilaritySingleEmbedding::<String>::default();
+        search_strategy.with_top_k(5);
+
+        let results = test_context
+            .pgv_storage
+            .retrieve_with_neighbors(&search_strategy, &query, 5)
+            .await
+            .unwrap();
+
+        // The row without a vector is neither a match nor a neighbor
+        assert_eq!(results.len(), 2);
+        for result in &results {
+            assert_ne!(result.document, "no vector");
+            assert_eq!(result.neighbors.len(), 1);
+            assert!(!result.neighbors.contains(&"no vector".to_string()));
+        }
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_retrieve_with_distance_matrix() {
+        let test_context = TestContext::setup_with_cfg(
+            vec!["filter"].into(),
+            HashSet::from([EmbeddedField::Combined]),
+        )
+        .await
+        .expect("Test setup failed");
+
+        let nodes = [1.0, 2.0, -1.0]
+            .into_iter()
+            .enumerate()
+            .map(|(i, first)| {
+                let mut vector = vec![1.0; 384];
+                vector[0] = first;
+                indexing::Node::new(format!("cluster_{i}"))
+                    .with_metadata(("filter", "true"))
+                    .with_vectors([(EmbeddedField::Combined, vector)])
+                    .to_owned()
+            })
+            .collect();
+
+        test_context
+            .pgv_storage
+            .batch_store(nodes)
+            .await
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        let mut query = Query::<states::Pending>::new("test_query");
+        query.embedding = Some(vec![1.0; 384]);
+
+        let search_strategy = SimilaritySingleEmbedding::<String>::default();
+
+        let result = test_context
+            .pgv_storage
+            .retrieve_with_distance_matrix(&search_strategy, &query)
+            .await
+            .unwrap();
+
+        let n = result.documents.len();
+        assert_eq!(n, 3);
+        assert_eq!(result.distances.len(), n);
+        for i in 0..n {
+            assert_eq!(result.distances[i].len(), n);
+            assert!(result.distances[i][i].abs() < f64::EPSILON);
+            for j in 0..n {
+                assert!((result.distances[i][j] - result.distances[j][i]).abs() < f64::EPSILON);
+                if i != j {
+                    assert!(result.distances[i][j] > 0.0);
+                }
+            }
+        }
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_retrieve_diversified_interleaves_sources() {
+        let test_context = TestContext::setup_with_cfg(
+            vec!["source"].into(),
+            HashSet::from([EmbeddedField::Combined]),
+        )
+        .await
+        .expect("Test setup failed");
+
+        // Source "a" dominates the closest results
+        let nodes = [
+            ("a_0", "a", 1.0),
+            ("a_1", "a", 1.1),
+            ("a_2", "a", 1.2),
+            ("a_3", "a", 1.3),
+            ("b_0", "b", 2.0),
+            ("b_1", "b", 2.5),
+        ]
+        .into_iter()
+        .map(|(chunk, source, first)| {
+            let mut vector = vec![1.0; 384];
+            vector[0] = first;
+            indexing::Node::new(chunk)
+                .with_metadata(("source", source))
+                .with_vectors([(EmbeddedField::Combined, vector)])
+                .to_owned()
+        })
+        .collect();
+
+        test_context
+            .pgv_storage
+            .batch_store(nodes)
+            .await
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        let mut query = Query::<states::Pending>::new("test_query");
+        query.embedding = Some(vec![1.0; 384]);
+
+        let mut search_strategy = SimilaritySingleEmbedding::<String>::default();
+        search_strategy.with_top_k(4);
+
+        let result = test_context
+            .pgv_storage
+            .retrieve(&search_strategy, query.clone())
+            .await
+            .unwrap();
+        assert_eq!(result.documents(), ["a_0", "a_1", "a_2", "a_3"]);
+
+        let result = test_context
+            .pgv_storage
+            .retrieve_diversified(
+                &search_strategy,
+                query,
+                &DiversityPenalty::new("source", 0.5),
+            )
+            .await
+            .unwrap();
+        assert_eq!(result.documents(), ["a_0", "b_0", "a_1", "b_1"]);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_retrieve_without_embedding_policies() {
+        for behavior in [
+            NoEmbeddingBehavior::Error,
+            NoEmbeddingBehavior::FilterOnly,
+            NoEmbeddingBehavior::Empty,
+        ] {
+            let test_context = TestContext::setup_with_builder(
+                vec!["filter"].into(),
+                HashSet::from([EmbeddedField::Combined]),
+                |builder| builder.no_embedding_behavior(behavior),
+            )
+            .await
+            .expect("Test setup failed");
+
+            let nodes = vec![
+                indexing::Node::new("match_1").with_metadata(("filter", "true")),
+                indexing::Node::new("match_2").with_metadata(("filter", "true")),
+                indexing::Node::new("other").with_metadata(("filter", "false")),
+            ]
+            .into_iter()
+            .map(|node| {
+                node.with_vectors([(EmbeddedField::Combined, vec![1.0; 384])]);
+                node.to_owned()
+            })
+            .collect();
+
+            test_context
+                .pgv_storage
+                .batch_store(nodes)
+                .await
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap();
+
+            let search_strategy =
+                SimilaritySingleEmbedding::from_filter("filter = \"true\"".to_string());
+            let query = Query::<states::Pending>::new("test_query");
+
+            let result = test_context
+                .pgv_storage
+                .retrieve(&search_strategy, query)
+                .await;
+
+            match behavior {
+                NoEmbeddingBehavior::Error => assert!(result.is_err()),
+                NoEmbeddingBehavior::FilterOnly => {
+                    let mut documents = result.unwrap().documents().to_vec();
+                    documents.sort();
+                    assert_eq!(documents, ["match_1", "match_2"]);
+                }
+                NoEmbeddingBehavior::Empty => assert!(result.unwrap().documents().is_empty()),
+            }
+        }
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_other_retrieves_honor_no_embedding_behavior() {
+        let test_context = TestContext::setup_with_builder(
+            vec!["filter"].into(),
+            HashSet::from([EmbeddedField::Combined]),
+            |builder| builder.no_embedding_behavior(NoEmbeddingBehavior::Empty),
+        )
+        .await
+        .expect("Test setup failed");
+
+        let node = indexing::Node::new("match")
+            .with_metadata(("filter", "true"))
+            .with_vectors([(EmbeddedField::Combined, vec![1.0; 384])])
+            .to_owned();
+        test_context.pgv_storage.store_nodes(&[node]).await.unwrap();
+
+        let search_strategy = SimilaritySingleEmbedding::<String>::default();
+        let query = Query::<states::Pending>::new("test_query");
+
+        let result = test_context
+            .pgv_storage
+            .retrieve_bool(
+                &search_strategy,
+                query.clone(),
+                &BoolQuery::new().must("filter", "true"),
+            )
+            .await
+            .unwrap();
+        assert!(result.documents().is_empty());
+
+        let rows = test_context
+            .pgv_storage
+            .retrieve_projected(&search_strategy, &query, &RetrieveOptions::default())
+            .await
+            .unwrap();
+        assert!(rows.is_empty());
+
+        let result = test_context
+            .pgv_storage
+            .retrieve_with_distance_matrix(&search_strategy, &query)
+            .await
+            .unwrap();
+        assert!(result.documents.is_empty());
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_retrieve_citations() {
+        let test_context = TestContext::setup_with_cfg(
+            vec!["source", "start", "end"].into(),
+            HashSet::from([EmbeddedField::Combined]),
+        )
+        .await
+        .expect("Test setup failed");
+
+        let nodes = vec![
+            indexing::Node::new("Hello world")
+                .with_metadata(vec![
+                    ("source", serde_json::json!("greeting.md")),
+                    ("start", serde_json::json!(0)),
+                    ("end", serde_json::json!(11)),
+                ])
+                .with_vectors([(EmbeddedField::Combined, vec![1.0; 384])])
+                .to_owned(),
+            indexing::Node::new("Goodbye")
+                .with_metadata(vec![
+                    ("source", serde_json::json!("greeting.md")),
+                    ("start", serde_json::json!(12)),
+                    ("end", serde_json::json!(19)),
+                ])
+                .with_vectors([(EmbeddedField::Combined, vec![-1.0; 384])])
+                .to_owned(),
+        ];
+
+        test_context
+            .pgv_storage
+            .batch_store(nodes)
+            .await
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        let mut query = Query::<states::Pending>::new("test_query");
+        query.embedding = Some(vec![1.0; 384]);
+
+        let search_strategy = SimilaritySingleEmbedding::<String>::default();
+
+        let citations = test_context
+            .pgv_storage
+            .retrieve_citations(
+                &search_strategy,
+                &query,
+                &CitationConfig::new("source", "start", "end"),
+            )
+            .await
+            .unwrap();
 
-        // Start building the SQL query
-        let mut sql = format!(
-            "SELECT {} FROM {}",
-            default_columns.join(", "),
-            self.table_name
+        assert_eq!(
+            citations,
+            vec![
+                Citation {
+                    source: "greeting.md".to_string(),
+                    start: 0,
+                    end: 11,
+                    text: "Hello world".to_string(),
+                },
+                Citation {
+                    source: "greeting.md".to_string(),
+                    start: 12,
+                    end: 19,
+                    text: "Goodbye".to_string(),
+                },
+            ]
         );
 
-        if let Some(filter) = search_strategy.filter() {
-            let filter_parts: Vec<&str> = filter.split('=').collect();
-            if filter_parts.len() == 2 {
-                let key = filter_parts[0].trim();
-                let value = filter_parts[1].trim().trim_matches('"');
-                tracing::debug!(
-                    "Filter being applied: key = {:#?}, value = {:#?}",
-                    key,
-                    value
-                );
-
-                let sql_filter = format!(
-                    " WHERE meta_{}->>'{}' = '{}'",
-                    PgVector::normalize_field_name(key),
-                    key,
-                    value
-                );
-                sql.push_str(&sql_filter);
-            } else {
-                return Err(anyhow!("Invalid filter format"));
-            }
+        // Offset fields must be configured
+        let result = test_context
+            .pgv_storage
+            .retrieve_citations(
+                &search_strategy,
+                &query,
+                &CitationConfig::new("source", "offset_start", "end"),
+            )
+            .await;
+        assert!(result.is_err());
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_retrieve_retries_serialization_failures() {
+        let test_context = TestContext::setup_with_builder(
+            None,
+            HashSet::from([EmbeddedField::Combined]),
+            |builder| builder.retrieve_retry_delay(std::time::Duration::from_millis(10)),
+        )
+        .await
+        .expect("Test setup failed");
+
+        let node = indexing::Node::new("eventually retrieved")
+            .with_vectors([(EmbeddedField::Combined, vec![1.0; 384])])
+            .to_owned();
+        test_context.pgv_storage.store_nodes(&[node]).await.unwrap();
+
+        // Put a view in front of the table that fails the first two reads with a serialization
+        // failure. The sequence is not transactional, so the count survives the failed reads.
+        let pool = test_context.pgv_storage.get_pool().await.unwrap();
+        for statement in [
+            "ALTER TABLE swiftide_pgvector_test RENAME TO swiftide_pgvector_base",
+            "CREATE SEQUENCE retrieve_attempts",
+            "CREATE FUNCTION fail_first_reads() RETURNS boolean LANGUAGE plpgsql VOLATILE AS $$ \
+             BEGIN \
+               IF nextval('retrieve_attempts') <= 2 THEN \
+                 RAISE EXCEPTION 'could not serialize access' USING ERRCODE = '40001'; \
+               END IF; \
+               RETURN true; \
+             END $$",
+            "CREATE VIEW swiftide_pgvector_test AS \
+             SELECT * FROM swiftide_pgvector_base WHERE fail_first_reads()",
+        ] {
+            sqlx::query(statement).execute(pool).await.unwrap();
         }
 
-        // Add the ORDER BY clause for vector similarity search
-        sql.push_str(&format!(
-            " ORDER BY {} <=> $1 LIMIT $2",
-            &vector_column_name
-        ));
+        let mut query = Query::<states::Pending>::new("test_query");
+        query.embedding = Some(vec![1.0; 384]);
 
-        tracing::debug!("Running retrieve with SQL: {}", sql);
+        let result = test_context
+            .pgv_storage
+            .retrieve(&SimilaritySingleEmbedding::<String>::default(), query)
+            .await
+            .unwrap();
+        assert_eq!(result.documents(), ["eventually retrieved"]);
 
-        let top_k = i32::try_from(search_strategy.top_k())
-            .map_err(|_| anyhow!("Failed to convert top_k to i32"))?;
+        let attempts: i64 = sqlx::query_scalar("SELECT last_value FROM retrieve_attempts")
+            .fetch_one(pool)
+            .await
+            .unwrap();
+        assert_eq!(attempts, 3);
 
-        let data: Vec<VectorSearchResult> = sqlx::query_as(&sql)
-            .bind(embedding)
-            .bind(top_k)
-            .fetch_all(pool)
-            .await?;
+        // The other retrieve methods retry as well
+        sqlx::query("ALTER SEQUENCE retrieve_attempts RESTART")
+            .execute(pool)
+            .await
+            .unwrap();
 
-        let docs = data.into_iter().map(|r| r.chunk).collect();
+        let result = test_context
+            .pgv_storage
+            .retrieve_within_radius(&[1.0; 384], 0.5)
+            .await
+            .unwrap();
+        assert_eq!(result, ["eventually retrieved"]);
 
-        Ok(query_state.retrieved_documents(docs))
+        let attempts: i64 = sqlx::query_scalar("SELECT last_value FROM retrieve_attempts")
+            .fetch_one(pool)
+            .await
+            .unwrap();
+        assert_eq!(attempts, 3);
     }
-}
 
-#[async_trait]
-impl Retrieve<SimilaritySingleEmbedding> for PgVector {
-    async fn retrieve(
-        &self,
-        search_strategy: &SimilaritySingleEmbedding,
-        query: Query<states::Pending>,
-    ) -> Result<Query<states::Retrieved>> {
-        Retrieve::<SimilaritySingleEmbedding<String>>::retrieve(
-            self,
-            &search_strategy.into_concrete_filter::<String>(),
-            query,
+    #[test_log::test(tokio::test)]
+    async fn test_retrieve_filtered_by_model() {
+        let test_context = TestContext::setup_with_builder(
+            None,
+            HashSet::from([EmbeddedField::Combined]),
+            |builder| builder.with_model("embedder@v1"),
         )
         .await
-    }
-}
+        .expect("Test setup failed");
 
-#[cfg(test)]
-mod tests {
-    use crate::pgvector::fixtures::TestContext;
-    use futures_util::TryStreamExt;
-    use std::collections::HashSet;
-    use swiftide_core::{indexing, indexing::EmbeddedField, Persist};
-    use swiftide_core::{
-        querying::{search_strategies::SimilaritySingleEmbedding, states, Query},
-        Retrieve,
-    };
+        let node = |chunk: &str| {
+            indexing::Node::new(chunk)
+                .with_vectors([(EmbeddedField::Combined, vec![1.0; 384])])
+                .to_owned()
+        };
+
+        test_context
+            .pgv_storage
+            .store_nodes(&[node("from_v1")])
+            .await
+            .unwrap();
+
+        // A second store on the same table, embedding with the next model version
+        let v2_storage = PgVector::builder()
+            .db_url(test_context.pgv_storage.db_url.clone())
+            .vector_size(384)
+            .table_name("swiftide_pgvector_test")
+            .with_vector(EmbeddedField::Combined)
+            .with_model("embedder@v2")
+            .build()
+            .unwrap();
+        v2_storage.setup().await.unwrap();
+        v2_storage.store_nodes(&[node("from_v2")]).await.unwrap();
+
+        let mut query = Query::<states::Pending>::new("test_query");
+        query.embedding = Some(vec![1.0; 384]);
+
+        let search_strategy =
+            SimilaritySingleEmbedding::from_filter("model = \"embedder@v2\"".to_string());
+
+        let result = test_context
+            .pgv_storage
+            .retrieve(&search_strategy, query)
+            .await
+            .unwrap();
+        assert_eq!(result.documents(), ["from_v2"]);
+    }
 
     #[test_log::test(tokio::test)]
-    async fn test_retrieve_multiple_docs_and_filter() {
-        let test_context = TestContext::setup_with_cfg(
-            vec!["filter"].into(),
+    async fn test_retrieve_grouped_by_parent() {
+        let test_context = TestContext::setup_with_builder(
+            None,
             HashSet::from([EmbeddedField::Combined]),
+            |builder| builder.with_metadata(MetadataConfig::new("parent_id").optional()),
         )
         .await
         .expect("Test setup failed");
 
-        let nodes = vec![
-            indexing::Node::new("test_query1").with_metadata(("filter", "true")),
-            indexing::Node::new("test_query2").with_metadata(("filter", "true")),
-            indexing::Node::new("test_query3").with_metadata(("filter", "false")),
+        let nodes = [
+            ("b_far", "doc_b", 1.5),
+            ("a_close", "doc_a", 1.0),
+            ("b_close", "doc_b", 1.1),
+            ("a_far", "doc_a", 1.3),
         ]
         .into_iter()
-        .map(|node| {
-            node.with_vectors([(EmbeddedField::Combined, vec![1.0; 384])]);
-            node.to_owned()
+        .map(|(chunk, parent, first)| {
+            let mut vector = vec![1.0; 384];
+            vector[0] = first;
+            indexing::Node::new(chunk)
+                .with_metadata(("parent_id", parent))
+                .with_vectors([(EmbeddedField::Combined, vector)])
+                .to_owned()
         })
+        .chain(std::iter::once(
+            indexing::Node::new("orphan")
+                .with_vectors([(EmbeddedField::Combined, vec![1.0; 384])])
+                .to_owned(),
+        ))
         .collect();
 
         test_context
@@ -151,34 +2250,418 @@ mod tests {
         let mut query = Query::<states::Pending>::new("test_query");
         query.embedding = Some(vec![1.0; 384]);
 
-        let search_strategy = SimilaritySingleEmbedding::<()>::default();
-        let result = test_context
+        let groups = test_context
             .pgv_storage
-            .retrieve(&search_strategy, query.clone())
+            .retrieve_grouped_by_parent(
+                &SimilaritySingleEmbedding::<String>::default(),
+                &query,
+                "parent_id",
+            )
             .await
             .unwrap();
 
-        assert_eq!(result.documents().len(), 3);
+        let nested: Vec<(&str, Vec<&str>)> = groups
+            .iter()
+            .map(|group| {
+                (
+                    group.parent_id.as_str(),
+                    group
+                        .children
+                        .iter()
+                        .map(|child| child.chunk.as_str())
+                        .collect(),
+                )
+            })
+            .collect();
 
-        let search_strategy =
+        assert_eq!(
+            nested,
+            vec![
+                ("doc_a", vec!["a_close", "a_far"]),
+                ("doc_b", vec!["b_close", "b_far"]),
+            ]
+        );
+        assert!(groups.iter().all(|group| group
+            .children
+            .windows(2)
+            .all(|w| w[0].distance <= w[1].distance)));
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_retrieve_with_exclude_ids() {
+        let test_context = TestContext::setup_with_cfg(
+            vec!["filter"].into(),
+            HashSet::from([EmbeddedField::Combined]),
+        )
+        .await
+        .expect("Test setup failed");
+
+        let nodes: Vec<_> = [("best", 1.0), ("second", 1.2), ("third", 1.4)]
+            .into_iter()
+            .map(|(chunk, first)| {
+                let mut vector = vec![1.0; 384];
+                vector[0] = first;
+                indexing::Node::new(chunk)
+                    .with_metadata(("filter", "true"))
+                    .with_vectors([(EmbeddedField::Combined, vector)])
+                    .to_owned()
+            })
+            .collect();
+
+        test_context.pgv_storage.store_nodes(&nodes).await.unwrap();
+
+        let mut query = Query::<states::Pending>::new("test_query");
+        query.embedding = Some(vec![1.0; 384]);
+
+        let mut search_strategy =
             SimilaritySingleEmbedding::from_filter("filter = \"true\"".to_string());
+        search_strategy.with_top_k(1);
 
         let result = test_context
             .pgv_storage
             .retrieve(&search_strategy, query.clone())
             .await
             .unwrap();
+        assert_eq!(result.documents(), ["best"]);
 
-        assert_eq!(result.documents().len(), 2);
+        let result = test_context
+            .pgv_storage
+            .retrieve_with_options(
+                &search_strategy,
+                query,
+                &RetrieveOptions::default().with_exclude_ids([nodes[0].id()]),
+            )
+            .await
+            .unwrap();
+        assert_eq!(result.documents(), ["second"]);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_retrieve_bool_query() {
+        let test_context = TestContext::setup_with_cfg(
+            vec!["category", "lang", "status"].into(),
+            HashSet::from([EmbeddedField::Combined]),
+        )
+        .await
+        .expect("Test setup failed");
+
+        let nodes: Vec<_> = [
+            (
+                "closest_en",
+                1.0,
+                vec![("category", "docs"), ("lang", "en")],
+            ),
+            (
+                "boosted_de",
+                1.2,
+                vec![("category", "docs"), ("lang", "de")],
+            ),
+            (
+                "archived",
+                1.0,
+                vec![("category", "docs"), ("lang", "de"), ("status", "archived")],
+            ),
+            (
+                "wrong_category",
+                1.0,
+                vec![("category", "blog"), ("lang", "de")],
+            ),
+        ]
+        .into_iter()
+        .map(|(chunk, first, metadata)| {
+            let mut vector = vec![1.0; 384];
+            vector[0] = first;
+            indexing::Node::new(chunk)
+                .with_metadata(metadata)
+                .with_vectors([(EmbeddedField::Combined, vector)])
+                .to_owned()
+        })
+        .collect();
+
+        test_context.pgv_storage.store_nodes(&nodes).await.unwrap();
+
+        let mut query = Query::<states::Pending>::new("test_query");
+        query.embedding = Some(vec![1.0; 384]);
+
+        let search_strategy = SimilaritySingleEmbedding::<String>::default();
+
+        // Without boosts, similarity decides
+        let bool_query = BoolQuery::new()
+            .must("category", "docs")
+            .must_not("status", "archived");
+
+        let result = test_context
+            .pgv_storage
+            .retrieve_bool(&search_strategy, query.clone(), &bool_query)
+            .await
+            .unwrap();
+        assert_eq!(result.documents(), ["closest_en", "boosted_de"]);
+
+        // The boost outweighs the distance difference
+        let result = test_context
+            .pgv_storage
+            .retrieve_bool(
+                &search_strategy,
+                query,
+                &bool_query.should("lang", "de", 0.5),
+            )
+            .await
+            .unwrap();
+        assert_eq!(result.documents(), ["boosted_de", "closest_en"]);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_retrieve_bool_rejects_non_finite_boost() {
+        // The database is unreachable, so the error must be raised before connecting
+        let pgv = PgVector::builder()
+            .db_url("postgresql://localhost:1/unreachable")
+            .vector_size(384)
+            .with_vector(EmbeddedField::Combined)
+            .with_metadata("lang")
+            .build()
+            .unwrap();
+
+        let mut query = Query::<states::Pending>::new("test_query");
+        query.embedding = Some(vec![1.0; 384]);
+
+        for boost in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            let err = pgv
+                .retrieve_bool(
+                    &SimilaritySingleEmbedding::<String>::default(),
+                    query.clone(),
+                    &BoolQuery::new().should("lang", "de", boost),
+                )
+                .await
+                .unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                format!("Boost of should clause `lang = de` must be finite, got {boost}")
+            );
+        }
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_retrieve_from_materialized_view() {
+        let test_context = TestContext::setup_with_builder(
+            None,
+            HashSet::from([EmbeddedField::Combined]),
+            |builder| builder.retrieve_from("swiftide_pgvector_titled"),
+        )
+        .await
+        .expect("Test setup failed");
+
+        let node = indexing::Node::new("chunk text")
+            .with_vectors([(EmbeddedField::Combined, vec![1.0; 384])])
+            .to_owned();
+
+        // Stores target the base table
+        test_context.pgv_storage.store_nodes(&[node]).await.unwrap();
+
+        // Precompute a join of the chunks with their document titles
+        let pool = test_context.pgv_storage.get_pool().await.unwrap();
+        for statement in [
+            "CREATE TABLE document_titles (title TEXT NOT NULL)",
+            "INSERT INTO document_titles VALUES ('Title')",
+            "CREATE MATERIALIZED VIEW swiftide_pgvector_titled AS \
+             SELECT s.id, d.title || ': ' || s.chunk AS chunk, s.vector_combined \
+             FROM swiftide_pgvector_test s CROSS JOIN document_titles d",
+        ] {
+            sqlx::query(statement).execute(pool).await.unwrap();
+        }
+
+        let mut query = Query::<states::Pending>::new("test_query");
+        query.embedding = Some(vec![1.0; 384]);
+
+        let result = test_context
+            .pgv_storage
+            .retrieve(&SimilaritySingleEmbedding::<String>::default(), query)
+            .await
+            .unwrap();
+        assert_eq!(result.documents(), ["Title: chunk text"]);
+    }
+
+    #[test]
+    fn test_parse_filter_quoted_values() {
+        let parse = |filter| PgVector::parse_filter(filter).map(|(k, v)| (k.to_string(), v));
+
+        // Nothing may follow the closing quote, escapes are unescaped
+        assert_eq!(
+            parse(r#"title = ""quoted"""#).unwrap_err().to_string(),
+            r#"Unexpected characters after quoted filter value: quoted"""#
+        );
+        assert_eq!(
+            parse(r#"title = "say \"hi\"""#).unwrap(),
+            ("title".to_string(), r#"say "hi""#.to_string())
+        );
+        assert_eq!(
+            parse(r#"path = "C:\\dir""#).unwrap(),
+            ("path".to_string(), r"C:\dir".to_string())
+        );
+
+        // Internal quotes in unquoted values and `=` in values are kept
+        assert_eq!(
+            parse(r#"name = O'Brien "Bob""#).unwrap(),
+            ("name".to_string(), r#"O'Brien "Bob""#.to_string())
+        );
+        assert_eq!(
+            parse("expr = a=b").unwrap(),
+            ("expr".to_string(), "a=b".to_string())
+        );
+
+        // Single character values
+        assert_eq!(parse("k = a").unwrap(), ("k".to_string(), "a".to_string()));
+        assert_eq!(
+            parse(r#"k = "a""#).unwrap(),
+            ("k".to_string(), "a".to_string())
+        );
+        assert_eq!(
+            parse(r#"k = "\"""#).unwrap(),
+            ("k".to_string(), "\"".to_string())
+        );
+        assert_eq!(
+            parse(r#"k = """#).unwrap(),
+            ("k".to_string(), String::new())
+        );
+        assert!(parse(r#"k = ""#).is_err());
+
+        assert!(parse("no separator").is_err());
+        assert!(parse(r#" = "value""#).is_err());
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_retrieve_filter_value_with_quotes() {
+        let test_context = TestContext::setup_with_cfg(
+            vec!["author"].into(),
+            HashSet::from([EmbeddedField::Combined]),
+        )
+        .await
+        .expect("Test setup failed");
+
+        let nodes: Vec<_> = [r#"O'Brien "Bob""#, "Bob"]
+            .into_iter()
+            .map(|author| {
+                indexing::Node::new(format!("by {author}"))
+                    .with_metadata(("author", author))
+                    .with_vectors([(EmbeddedField::Combined, vec![1.0; 384])])
+                    .to_owned()
+            })
+            .collect();
+
+        test_context.pgv_storage.store_nodes(&nodes).await.unwrap();
+
+        let mut query = Query::<states::Pending>::new("test_query");
+        query.embedding = Some(vec![1.0; 384]);
 
         let search_strategy =
-            SimilaritySingleEmbedding::from_filter("filter = \"banana\"".to_string());
+            SimilaritySingleEmbedding::from_filter(r#"author = "O'Brien \"Bob\"""#.to_string());
 
         let result = test_context
             .pgv_storage
-            .retrieve(&search_strategy, query.clone())
+            .retrieve(&search_strategy, query)
             .await
             .unwrap();
-        assert_eq!(result.documents().len(), 0);
+        assert_eq!(result.documents(), [r#"by O'Brien "Bob""#]);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_retrieve_projected_metadata_and_score() {
+        let test_context = TestContext::setup_with_cfg(
+            vec!["author", "source"].into(),
+            HashSet::from([EmbeddedField::Combined]),
+        )
+        .await
+        .expect("Test setup failed");
+
+        let node = indexing::Node::new("projected chunk")
+            .with_metadata(vec![("author", "alice"), ("source", "docs")])
+            .with_vectors([(EmbeddedField::Combined, vec![1.0; 384])])
+            .to_owned();
+
+        test_context
+            .pgv_storage
+            .store_nodes(&[node.clone()])
+            .await
+            .unwrap();
+
+        let mut query = Query::<states::Pending>::new("test_query");
+        query.embedding = Some(vec![1.0; 384]);
+
+        let rows = test_context
+            .pgv_storage
+            .retrieve_projected(
+                &SimilaritySingleEmbedding::<String>::default(),
+                &query,
+                &RetrieveOptions::default()
+                    .with_projection(Projection::new().id().score().metadata("author")),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(rows.len(), 1);
+        let row = &rows[0];
+        assert_eq!(row.id, Some(node.id()));
+        assert!((row.score.unwrap() - 1.0).abs() < 1e-6);
+        assert_eq!(row.chunk, None);
+        assert_eq!(
+            row.metadata,
+            [("author".to_string(), serde_json::json!("alice"))].into()
+        );
+
+        // Without a projection, the ID, chunk and score are returned
+        let rows = test_context
+            .pgv_storage
+            .retrieve_projected(
+                &SimilaritySingleEmbedding::<String>::default(),
+                &query,
+                &RetrieveOptions::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(rows[0].chunk.as_deref(), Some("projected chunk"));
+        assert!(rows[0].metadata.is_empty());
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_retrieve_with_tie_break() {
+        let test_context = TestContext::setup_with_cfg(
+            vec!["popularity"].into(),
+            HashSet::from([EmbeddedField::Combined]),
+        )
+        .await
+        .expect("Test setup failed");
+
+        // All nodes have the same vector, so their distances tie
+        let nodes: Vec<_> = [("ten", 10), ("two", 2), ("thirty", 30)]
+            .into_iter()
+            .map(|(chunk, popularity)| {
+                indexing::Node::new(chunk)
+                    .with_metadata(("popularity", popularity))
+                    .with_vectors([(EmbeddedField::Combined, vec![1.0; 384])])
+                    .to_owned()
+            })
+            .collect();
+
+        test_context.pgv_storage.store_nodes(&nodes).await.unwrap();
+
+        let mut query = Query::<states::Pending>::new("test_query");
+        query.embedding = Some(vec![1.0; 384]);
+
+        let search_strategy = SimilaritySingleEmbedding::<String>::default();
+
+        for (order, expected) in [
+            (SortOrder::Descending, ["thirty", "ten", "two"]),
+            (SortOrder::Ascending, ["two", "ten", "thirty"]),
+        ] {
+            let result = test_context
+                .pgv_storage
+                .retrieve_with_options(
+                    &search_strategy,
+                    query.clone(),
+                    &RetrieveOptions::default().with_tie_break("popularity", order),
+                )
+                .await
+                .unwrap();
+            assert_eq!(result.documents(), expected);
+        }
     }
 }
@@ -0,0 +1,225 @@
+//! Resumable full-table scans for exporting stored nodes.
+//!
+//! Scans page through the table in ID order using a keyset cursor:
+//! - Each page is fetched with `WHERE id > <last emitted id>`, so no offsets are involved
+//! - Transient connection errors are retried, resuming after the last emitted ID
+//! - Rows are converted back into [`Node`]s together with their stored ID
+use crate::pgvector::PgVector;
+use anyhow::{anyhow, Result};
+use futures_util::{stream, Stream, TryStreamExt};
+use sqlx::types::Uuid;
+use swiftide_core::indexing::Node;
+use tokio::time::sleep;
+
+/// A node read back from the table, together with its stored ID.
+///
+/// The stored ID is derived from the original node's path and chunk. As the path is not
+/// persisted, it can differ from `node.id()`.
+#[derive(Clone, Debug)]
+pub struct StoredNode {
+    /// The ID of the stored row
+    pub id: Uuid,
+    /// The node reconstructed from the stored chunk, metadata and vectors
+    pub node: Node,
+}
+
+impl PgVector {
+    /// Scans all stored nodes in ID order, fetching `page_size` rows at a time.
+    ///
+    /// The scan uses a keyset cursor on the ID. If fetching a page fails with a transient
+    /// connection error, the page is retried on a fresh connection up to `scan_max_reconnects`
+    /// times, continuing after the last emitted ID.
+    ///
+    /// # Errors
+    ///
+    /// The stream yields an error if `page_size` is zero, a page fails with a non-transient error,
+    /// or reconnect attempts are exhausted.
+    pub fn scan_all(&self, page_size: usize) -> impl Stream<Item = Result<StoredNode>> + '_ {
+        stream::try_unfold(
+            (None::<Uuid>, false),
+            move |(cursor, exhausted)| async move {
+                if exhausted {
+                    return Ok(None);
+                }
+
+                let page = self.fetch_scan_page(cursor, page_size).await?;
+                let Some(last) = page.last() else {
+                    return Ok(None);
+                };
+
+                let cursor = Some(last.id);
+                let exhausted = page.len() < page_size;
+
+                Ok::<_, anyhow::Error>(Some((page, (cursor, exhausted))))
+            },
+        )
+        .map_ok(|page| stream::iter(page.into_iter().map(Ok)))
+        .try_flatten()
+    }
+
+    /// Fetches the page of rows following `after`, retrying transient connection errors.
+    async fn fetch_scan_page(
+        &self,
+        after: Option<Uuid>,
+        page_size: usize,
+    ) -> Result<Vec<StoredNode>> {
+        if page_size == 0 {
+            return Err(anyhow!("Scan page size must be greater than zero"));
+        }
+
+        let limit =
+            i64::try_from(page_size).map_err(|_| anyhow!("Failed to convert page_size to i64"))?;
+
+        let sql = format!(
+            "SELECT {} FROM {} WHERE ($1::uuid IS NULL OR id > $1) ORDER BY id LIMIT $2",
+            self.node_select_columns().join(", "),
+            self.table_name
+        );
+
+        let mut attempt = 0;
+        loop {
+            let pool = self.pool_get_or_initialize().await?;
+
+            match sqlx::query(&sql)
+                .bind(after)
+                .bind(limit)
+                .fetch_all(pool)
+                .await
+            {
+                Ok(rows) => {
+                    return rows
+                        .iter()
+                        .map(|row| {
+                            Ok(StoredNode {
+                                id: sqlx::Row::try_get(row, "id")?,
+                                node: self.node_from_row(row)?,
+                            })
+                        })
+                        .collect();
+                }
+                Err(err)
+                    if attempt < self.scan_max_reconnects
+                        && Self::is_transient_connection_error(&err) =>
+                {
+                    attempt += 1;
+                    tracing::warn!(
+                        error = %err,
+                        attempt = attempt,
+                        max_reconnects = self.scan_max_reconnects,
+                        "Scan lost its connection, resuming after last emitted id..."
+                    );
+                    sleep(self.db_conn_retry_delay).await;
+                }
+                Err(err) => return Err(anyhow!(err).context("Failed to fetch scan page")),
+            }
+        }
+    }
+
+    /// Returns true if the error indicates a lost or unavailable connection.
+    pub(crate) fn is_transient_connection_error(err: &sqlx::Error) -> bool {
+        match err {
+            sqlx::Error::Io(_) | sqlx::Error::PoolTimedOut | sqlx::Error::Protocol(_) => true,
+            // Class 08 is connection exception, 57P01-57P03 are shutdown and cannot connect now
+            sqlx::Error::Database(db_err) => db_err.code().is_some_and(|code| {
+                code.starts_with("08") || matches!(code.as_ref(), "57P01" | "57P02" | "57P03")
+            }),
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pgvector::fixtures::TestContext;
+    use futures_util::{StreamExt, TryStreamExt};
+    use sqlx::Connection;
+    use std::collections::HashSet;
+    use swiftide_core::{indexing, indexing::EmbeddedField, Persist};
+
+    #[test_log::test(tokio::test)]
+    async fn test_scan_all_resumes_after_connection_drop() {
+        let test_context = TestContext::setup_with_builder(
+            None,
+            HashSet::from([EmbeddedField::Combined]),
+            |builder| builder.db_conn_retry_delay(std::time::Duration::from_millis(100)),
+        )
+        .await
+        .expect("Test setup failed");
+
+        let nodes: Vec<indexing::Node> = (0..5)
+            .map(|i| {
+                indexing::Node::new(format!("scan_{i}"))
+                    .with_vectors([(EmbeddedField::Combined, vec![1.0; 384])])
+                    .to_owned()
+            })
+            .collect();
+
+        test_context
+            .pgv_storage
+            .batch_store(nodes)
+            .await
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+
+        let mut scan = Box::pin(test_context.pgv_storage.scan_all(2));
+
+        let mut ids = vec![scan.next().await.unwrap().unwrap().id];
+
+        // Drop all pooled connections mid-stream from a separate connection
+        let mut admin = sqlx::PgConnection::connect(&test_context.pgv_storage.db_url)
+            .await
+            .unwrap();
+        sqlx::query(
+            "SELECT pg_terminate_backend(pid) FROM pg_stat_activity \
+             WHERE datname = current_database() AND pid <> pg_backend_pid()",
+        )
+        .execute(&mut admin)
+        .await
+        .unwrap();
+
+        while let Some(stored) = scan.next().await {
+            ids.push(
+                stored
+                    .expect("Scan should survive the dropped connection")
+                    .id,
+            );
+        }
+
+        assert_eq!(ids.len(), 5);
+        assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_metadata_round_trip_preserves_json_types() {
+        let test_context = TestContext::setup_with_cfg(
+            vec!["count", "published", "source"].into(),
+            HashSet::from([EmbeddedField::Combined]),
+        )
+        .await
+        .expect("Test setup failed");
+
+        let node = indexing::Node::new("typed metadata")
+            .with_metadata(vec![
+                ("count", serde_json::json!(42)),
+                ("published", serde_json::json!(true)),
+                ("source", serde_json::json!("docs")),
+            ])
+            .with_vectors([(EmbeddedField::Combined, vec![1.0; 384])])
+            .to_owned();
+
+        test_context.pgv_storage.store_nodes(&[node]).await.unwrap();
+
+        let stored: Vec<_> = test_context
+            .pgv_storage
+            .scan_all(10)
+            .try_collect()
+            .await
+            .unwrap();
+
+        let metadata = &stored[0].node.metadata;
+        assert_eq!(metadata.get("count"), Some(&serde_json::json!(42)));
+        assert_eq!(metadata.get("published"), Some(&serde_json::json!(true)));
+        assert_eq!(metadata.get("source"), Some(&serde_json::json!("docs")));
+    }
+}